@@ -1,20 +1,43 @@
-use std::error;
+use std::{
+    collections::HashMap,
+    error,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use chrono::Utc;
 use crossterm::event::KeyEvent;
-use ratatui::{
-    style::{Color, Style},
-    Frame,
-};
+use ratatui::{style::Style, Frame};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tui_textarea::TextArea;
+use zeroize::{Zeroize, Zeroizing};
 
 use crate::{
-    tcp::{AccountList, Ack, Action},
-    ui::running::RunningPage,
+    config::{Config, Profile},
+    event::Event,
+    form::{validate_master_password, FieldError, FormValues, MasterField},
+    item::ItemType,
+    keymap::{Context, KeyPress, Keymap},
+    login::{self, Backoff},
+    secret::{SecretCache, Secrets},
+    tcp::{
+        AccountList, AccountListItem, Ack, Action, Capability, ChangeEvent, Connect, CustomField,
+        ListFilter,
+    },
+    ui::{running::RunningPage, theme::Theme},
 };
 
+/// How long to wait before subscribing again to the pushed changes.
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+
+/// How long the server may take to check the links, visiting every site.
+const DEAD_LINK_TIMEOUT: Duration = Duration::from_secs(300);
+
 /// Application result type.
 pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Focus {
     List,
     Account,
@@ -22,6 +45,46 @@ pub enum Focus {
     Url,
     Note,
     Password,
+    Folder,
+    Tags,
+    /// The name of the custom field at this index.
+    CustomName(usize),
+    /// The value of the custom field at this index.
+    CustomValue(usize),
+    /// The field of the item type's schema at this index.
+    TypeField(usize),
+}
+
+impl Focus {
+    /// The edit form fields of a login, in tab order.
+    pub const EDIT_FIELDS: [Focus; 7] = [
+        Focus::Account,
+        Focus::Name,
+        Focus::Password,
+        Focus::Url,
+        Focus::Folder,
+        Focus::Tags,
+        Focus::Note,
+    ];
+
+    /// The edit form fields of an `item_type` item with `custom_fields` custom
+    /// fields, in tab order.
+    pub fn edit_fields(item_type: ItemType, custom_fields: usize) -> Vec<Focus> {
+        let mut fields = match item_type {
+            ItemType::Login => Focus::EDIT_FIELDS.to_vec(),
+            _ => {
+                let mut fields = vec![Focus::Name];
+                fields.extend((0..item_type.fields().len()).map(Focus::TypeField));
+                fields.extend([Focus::Folder, Focus::Tags, Focus::Note]);
+                fields
+            }
+        };
+        for i in 0..custom_fields {
+            fields.push(Focus::CustomName(i));
+            fields.push(Focus::CustomValue(i));
+        }
+        fields
+    }
 }
 
 #[derive(Debug)]
@@ -31,6 +94,46 @@ pub enum Status {
     Edit,
     Delete,
     Search,
+    /// Asking whether to throw away the changes in the edit form.
+    Discard,
+    /// Asking which URL of the selected item to open.
+    PickUrl,
+    /// Browsing the password history of the selected item.
+    History,
+    /// Browsing the deleted items.
+    Trash,
+    /// Asking whether to delete the highlighted trash item for good.
+    Purge,
+    /// Typing the tags to add to or remove from the marked items.
+    BulkTag,
+    /// Changing the master password.
+    MasterPassword,
+}
+
+/// How long a [`Toast`] stays on screen, and with it the chance to undo.
+const TOAST_DURATION: Duration = Duration::from_secs(8);
+
+/// A short message at the bottom of the screen that goes away by itself.
+#[derive(Debug, Clone)]
+pub struct Toast {
+    pub message: String,
+    /// The deleted items the undo command brings back.
+    pub undo: Vec<i32>,
+    pub shown_at: Instant,
+}
+
+/// What is kept of a profile's vault while another profile is shown.
+#[derive(Debug, Default)]
+struct Session {
+    unlocked: bool,
+    account_list: AccountList,
+    trash: Vec<AccountListItem>,
+    folder: Option<String>,
+    recent: bool,
+    conn: Option<Arc<Connect>>,
+    legacy_server: bool,
+    lazy_passwords: bool,
+    login_backoff: Backoff,
 }
 
 /// Application.
@@ -46,7 +149,76 @@ pub struct App {
     /// Is the application running?
     pub running: bool,
     pub account_list: AccountList,
+    /// Which of the selected item's URLs is highlighted in [`Status::PickUrl`].
+    pub url_choice: usize,
+    /// Which old password is highlighted in [`Status::History`].
+    pub history_choice: usize,
+    /// The item whose password history is browsed, with its secrets.
+    pub history_item: Option<Zeroizing<AccountListItem>>,
+    /// Are the old passwords shown in clear text?
+    pub reveal_history: bool,
+    /// Is the list showing recently used items only?
+    pub recent: bool,
+    /// Where the range being marked started, while visual marking is on.
+    pub visual_anchor: Option<usize>,
+    /// The folder the list is narrowed to, every item when `None`.
+    pub folder: Option<String>,
+    /// The deleted items, as last fetched for [`Status::Trash`].
+    pub trash: Vec<AccountListItem>,
+    /// Which trash item is highlighted.
+    pub trash_choice: usize,
+    /// How long deleted items are kept, for ever when `None`.
+    pub trash_retention: Option<chrono::Duration>,
+    pub toast: Option<Toast>,
     pub page: RunningPage<'static>,
+    pub keymap: Keymap,
+    /// Keys of a multi-key binding typed so far.
+    pub pending_keys: Vec<KeyPress>,
+    /// Is the help overlay shown?
+    pub show_help: bool,
+    /// How far the note in the detail view is scrolled down.
+    pub note_scroll: u16,
+    /// When and on which list item the last click landed, to spot double clicks.
+    pub last_click: Option<(Instant, usize)>,
+    /// The edit form as it was opened, to tell whether it has been changed.
+    pub form_original: FormValues,
+    /// Why the last attempt to save the edit form was refused.
+    pub form_errors: Vec<FieldError>,
+    /// The id of the item the edit form changes.
+    pub editing_id: Option<i32>,
+    /// Set when the item being edited changed on the server meanwhile.
+    pub edit_warning: Option<String>,
+    /// Where changes pushed by the server are sent, see [`App::subscribe`].
+    pub events: Option<mpsc::UnboundedSender<Event>>,
+    /// The focused input of the change master password screen.
+    pub master_focus: MasterField,
+    /// Why the master password was not changed.
+    pub master_error: Option<(MasterField, String)>,
+    /// The connection kept for all requests, if the server takes several at once.
+    pub conn: Option<Arc<Connect>>,
+    /// Did the server not know the hello? It is then not asked again.
+    pub legacy_server: bool,
+    /// Does the list come without passwords, fetched one by one when needed?
+    pub lazy_passwords: bool,
+    /// The passwords fetched lately.
+    pub secrets: SecretCache,
+    /// The vault servers to choose from, never empty.
+    pub profiles: Vec<Profile>,
+    /// Which of `profiles` is shown.
+    pub profile: usize,
+    /// The sessions of the other profiles, by index into `profiles`.
+    sessions: HashMap<usize, Session>,
+    /// The task listening for pushed changes, see [`App::subscribe`].
+    subscription: Option<JoinHandle<()>>,
+    /// Does confirming [`Status::Delete`] move the items to the trash, rather
+    /// than delete them for good?
+    pub delete_to_trash: bool,
+    /// Why the last attempt to unlock failed.
+    pub login_error: Option<String>,
+    /// The wrong passwords so far and the wait they earned.
+    pub login_backoff: Backoff,
+    /// Does the screen need to be redrawn?
+    render_requested: bool,
 }
 
 impl Default for App {
@@ -61,7 +233,42 @@ impl Default for App {
             status: Status::Login,
             running: true,
             account_list: AccountList::default(),
+            url_choice: 0,
+            history_choice: 0,
+            history_item: None,
+            reveal_history: false,
+            recent: false,
+            visual_anchor: None,
+            folder: None,
+            trash: Vec::new(),
+            trash_choice: 0,
+            trash_retention: crate::config::TrashConfig::default().retention(),
+            toast: None,
             page: RunningPage::default(),
+            keymap: Keymap::default(),
+            pending_keys: Vec::new(),
+            show_help: false,
+            note_scroll: 0,
+            last_click: None,
+            form_original: FormValues::default(),
+            form_errors: Vec::new(),
+            editing_id: None,
+            edit_warning: None,
+            events: None,
+            master_focus: MasterField::Current,
+            master_error: None,
+            conn: None,
+            legacy_server: false,
+            lazy_passwords: false,
+            secrets: SecretCache::default(),
+            profiles: vec![Profile::default()],
+            profile: 0,
+            sessions: HashMap::new(),
+            subscription: None,
+            delete_to_trash: true,
+            login_error: None,
+            login_backoff: Backoff::default(),
+            render_requested: true,
         }
     }
 }
@@ -72,13 +279,85 @@ impl App {
         Self::default()
     }
 
+    /// Constructs a new instance of [`App`] set up from the user's [`Config`].
+    pub fn with_config(config: &Config) -> AppResult<Self> {
+        Ok(Self {
+            keymap: Keymap::from_config(&config.keymap)?,
+            page: RunningPage::new(Theme::from_config(&config.theme)?),
+            trash_retention: config.trash.retention(),
+            profiles: config.profiles(),
+            ..Self::default()
+        })
+    }
+
     /// Handles the tick event of the terminal.
-    pub fn tick(&self) {}
+    ///
+    /// Timers live here; whatever they change has to call [`App::request_render`].
+    pub fn tick(&mut self) {
+        self.secrets.expire();
+        // Counts the wait down.
+        if matches!(self.status, Status::Login)
+            && self.login_backoff.remaining(Instant::now()).is_some()
+        {
+            self.request_render();
+        }
+        if self
+            .toast
+            .as_ref()
+            .is_some_and(|toast| toast.shown_at.elapsed() >= TOAST_DURATION)
+        {
+            self.toast = None;
+            self.request_render();
+        }
+    }
+
+    /// Show `message` for a while, with the items `undo` restores if any.
+    pub fn show_toast(&mut self, message: impl Into<String>, undo: Vec<i32>) {
+        self.toast = Some(Toast {
+            message: message.into(),
+            undo,
+            shown_at: Instant::now(),
+        });
+    }
+
+    /// Ask for the screen to be redrawn before the next event is handled.
+    pub fn request_render(&mut self) {
+        self.render_requested = true;
+    }
+
+    /// Whether a render was requested since the last call, clearing the request.
+    pub fn take_render_request(&mut self) -> bool {
+        std::mem::take(&mut self.render_requested)
+    }
 
     /// Set running to false to quit the application.
     pub fn quit(&mut self) {
         self.running = false;
     }
+
+    /// The keymap context for the current status and focus.
+    pub fn key_context(&self) -> Context {
+        match self.status {
+            Status::Login => Context::Login,
+            Status::Delete => Context::Delete,
+            Status::Search => Context::Search,
+            Status::Discard => Context::Discard,
+            Status::PickUrl => Context::PickUrl,
+            Status::History => Context::History,
+            Status::Trash => Context::Trash,
+            Status::Purge => Context::Purge,
+            Status::BulkTag => Context::BulkTag,
+            Status::MasterPassword => Context::MasterPassword,
+            Status::List | Status::Edit => match self.focus {
+                Focus::List => Context::List,
+                _ => Context::Edit,
+            },
+        }
+    }
+
+    pub fn toggle_help(&mut self) {
+        self.show_help = !self.show_help;
+    }
 }
 
 impl App {
@@ -90,264 +369,648 @@ impl App {
         self.page.login_textarea.input(key);
     }
 
-    pub async fn sync(&mut self) {
-        let conn = crate::tcp::Connect::new().await;
-        let act = Action::GetInfo;
-        let res = conn.request(act).await;
-        if let Ok(Ack::Info { list }) = res {
-            self.account_list.list = list;
+    /// The line under the password box: why unlocking failed, and how long
+    /// until the next attempt.
+    pub fn login_message(&self) -> Option<String> {
+        let error = self.login_error.as_deref()?;
+        match self.login_backoff.remaining(Instant::now()) {
+            Some(wait) => Some(format!(
+                "{}, try again in {}s",
+                error,
+                wait.as_secs_f32().ceil()
+            )),
+            None => Some(error.to_string()),
         }
     }
 
-    pub async fn login(&mut self) {
-        let password = self
-            .page
-            .login_textarea
-            .lines()
-            .first()
-            .unwrap_or(&"".to_string())
-            .clone();
-
-        let conn = crate::tcp::Connect::new().await;
-        let act = Action::CheckIdentity { password };
-        let res = conn.request(act).await;
-        match res {
-            Ok(Ack::Ack) => {
-                // self.login = true;
-                self.status = Status::List;
-                self.page
-                    .login_textarea
-                    .set_style(Style::default().fg(Color::Green));
-                self.sync().await;
+    /// A guess at what is off with what is being typed, like Caps Lock being on.
+    pub fn login_hint(&self) -> Option<&'static str> {
+        let password = Zeroizing::new(self.page.login_textarea.lines().join(""));
+        login::keyboard_hint(&password)
+    }
+
+    /// Open a new connection to the server, saying hello only if it did not
+    /// turn out to be from before the handshake already.
+    async fn open_connection(&mut self) -> AppResult<Connect> {
+        if self.legacy_server {
+            return Connect::legacy(self.current_profile()).await;
+        }
+        let conn = Connect::new(self.current_profile()).await?;
+        self.legacy_server = conn.server.is_legacy();
+        Ok(conn)
+    }
+
+    /// Connect to the server, telling the user why in a toast if that fails.
+    ///
+    /// A pipelined connection is kept and shared by every request after it.
+    async fn connect(&mut self) -> Option<Arc<Connect>> {
+        if let Some(conn) = self.conn.as_ref().filter(|conn| !conn.is_closed()) {
+            return Some(conn.clone());
+        }
+        match self.open_connection().await {
+            Ok(conn) => {
+                let conn = Arc::new(conn);
+                self.conn = conn.is_pipelined().then(|| conn.clone());
+                Some(conn)
             }
-            _ => {
-                self.page
-                    .login_textarea
-                    .set_style(Style::default().fg(Color::Red));
-                self.page
-                    .login_textarea
-                    .set_placeholder_text("Wrong password");
+            Err(e) => {
+                self.show_toast(e.to_string(), Vec::new());
+                None
             }
         }
     }
 
-    pub fn list_select_next_item(&mut self) {
-        let len = self.account_list.list.len();
-        if len == 0 {
+    /// Bring the list up to date with the server, fetching only what changed
+    /// since the last sync when the server can tell.
+    pub async fn sync(&mut self) {
+        let Some(conn) = self.connect().await else {
             return;
+        };
+        // Passwords may have changed with the list.
+        self.lazy_passwords = conn.supports(Capability::LazyPasswords);
+        self.secrets.clear();
+        if !conn.supports(Capability::DeltaSync) {
+            if let Ok(Ack::Info { list }) = conn.request(Action::GetInfo).await {
+                self.account_list.replace(list);
+                self.account_list.revision = None;
+            }
+            self.keep_selection_visible();
+            return;
+        }
+        let mut since = self.account_list.revision.unwrap_or(0);
+        let mut res = conn
+            .request(Action::GetChangesSince { revision: since })
+            .await;
+        if since != 0 && matches!(res, Ok(Ack::RevisionTooOld)) {
+            since = 0;
+            res = conn.request(Action::GetChangesSince { revision: 0 }).await;
         }
-        if let Some(res) = self.account_list.selected.checked_add(1) {
-            self.account_list.selected = res % len;
+        if let Ok(Ack::Changes {
+            revision,
+            changed,
+            removed,
+        }) = res
+        {
+            // Changes since 0 are the whole vault.
+            if since == 0 {
+                self.account_list.replace(changed);
+            } else {
+                self.account_list.apply_changes(changed, &removed);
+            }
+            self.account_list.revision = Some(revision);
         }
+        self.keep_selection_visible();
     }
 
-    pub fn list_select_before_item(&mut self) {
-        let len = self.account_list.list.len();
-        if len == 0 {
+    /// Listen for the changes the server pushes, as [`Event::VaultChanged`].
+    ///
+    /// A lost subscription is opened again after a while; changes made in
+    /// between show up with the next one.
+    async fn subscribe(&mut self) {
+        let Some(events) = self.events.clone() else {
+            return;
+        };
+        let Some(conn) = self.connect().await else {
+            return;
+        };
+        if !conn.supports(Capability::Push) {
             return;
         }
-        if let Some(res) = self.account_list.selected.checked_sub(1) {
-            self.account_list.selected = res % len;
-        } else {
-            self.account_list.selected = len - 1;
+        let profile = self.current_profile().clone();
+        if let Some(subscription) = self.subscription.take() {
+            subscription.abort();
         }
+        self.subscription = Some(tokio::spawn(async move {
+            while !events.is_closed() {
+                // The error is not `Send`, so it must not live across the waiting.
+                let subscription = Connect::subscribe(&profile).await.ok();
+                if let Some(mut subscription) = subscription {
+                    while let Ok(change) = subscription.next().await {
+                        if events.send(Event::VaultChanged(change)).is_err() {
+                            return;
+                        }
+                    }
+                }
+                tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+            }
+        }));
     }
 
-    pub fn focus_next(&mut self) {
-        self.focus = match self.focus {
-            Focus::Account => {
-                self.page
-                    .account_textarea
-                    .set_cursor_style(Style::default());
-                self.page
-                    .site_name_textarea
-                    .set_cursor_style(Style::default().bg(Color::White));
-                Focus::Name
-            }
-            Focus::Name => {
-                self.page
-                    .site_name_textarea
-                    .set_cursor_style(Style::default());
-                self.page
-                    .password_textarea
-                    .set_cursor_style(Style::default().bg(Color::White));
-                Focus::Password
+    /// The server says the vault changed: refresh the list, and warn if the
+    /// item in the edit form is among the changes.
+    pub async fn vault_changed(&mut self, change: ChangeEvent) {
+        // Our own changes are synced already.
+        if self
+            .account_list
+            .revision
+            .is_some_and(|revision| revision >= change.revision)
+        {
+            return;
+        }
+        if let Some(id) = self.editing_id {
+            if change.removed.contains(&id) {
+                self.edit_warning = Some("Deleted on the server while you were editing".into());
+            } else if change.changed.contains(&id) {
+                self.edit_warning =
+                    Some("Changed on the server, saving overwrites those changes".into());
             }
-            Focus::Password => {
-                self.page
-                    .password_textarea
-                    .set_cursor_style(Style::default());
-                self.page
-                    .site_url_textarea
-                    .set_cursor_style(Style::default().bg(Color::White));
-                Focus::Url
+        }
+        self.sync().await;
+        self.request_render();
+    }
+
+    /// The secrets of the item at `index`, fetched from the server if the
+    /// list came without them.
+    async fn fetch_secrets(&mut self, index: usize) -> Option<Zeroizing<Secrets>> {
+        let item = self.account_list.list.get(index)?;
+        if !self.lazy_passwords {
+            return Some(Zeroizing::new(Secrets::of(item)));
+        }
+        let id = item.id?;
+        if let Some(secrets) = self.secrets.get(id) {
+            return Some(secrets);
+        }
+        let conn = self.connect().await?;
+        let action = Action::GetWebsiteAccountPassword { website_id: id };
+        match conn.request(action).await {
+            Ok(Ack::Password {
+                password,
+                type_fields,
+                password_history,
+            }) => {
+                let secrets = Zeroizing::new(Secrets {
+                    password,
+                    type_fields,
+                    password_history,
+                });
+                self.secrets.insert(id, secrets.clone());
+                Some(secrets)
             }
-            Focus::Url => {
-                self.page
-                    .site_url_textarea
-                    .set_cursor_style(Style::default());
-                self.page
-                    .note_textarea
-                    .set_cursor_style(Style::default().bg(Color::White));
-                Focus::Note
+            _ => {
+                self.show_toast("Could not fetch the password", Vec::new());
+                None
             }
-            Focus::Note => {
-                self.page.note_textarea.set_cursor_style(Style::default());
-                self.page
-                    .account_textarea
-                    .set_cursor_style(Style::default().bg(Color::White));
-                Focus::Account
+        }
+    }
+
+    /// A copy of the item at `index` with its secrets, for sending it back
+    /// whole or showing it in full. Wiped from memory when dropped.
+    async fn item_with_secrets(&mut self, index: usize) -> Option<Zeroizing<AccountListItem>> {
+        let secrets = self.fetch_secrets(index).await?;
+        Some(secrets.reveal(self.account_list.list.get(index)?))
+    }
+
+    pub async fn login(&mut self) {
+        if self.login_backoff.remaining(Instant::now()).is_some() {
+            return;
+        }
+        let password = Zeroizing::new(
+            self.page
+                .login_textarea
+                .lines()
+                .first()
+                .cloned()
+                .unwrap_or_default(),
+        );
+
+        // Only wrong passwords count towards the backoff.
+        let error = match self.open_connection().await {
+            Ok(conn) => match conn
+                .request(Action::CheckIdentity {
+                    password: password.to_string(),
+                })
+                .await
+            {
+                Ok(Ack::Ack) => None,
+                Ok(Ack::IdentityError) => {
+                    self.login_backoff.fail(Instant::now());
+                    Some(match self.login_backoff.failures {
+                        1 => "Wrong password".to_string(),
+                        failures => format!("Wrong password, {} attempts", failures),
+                    })
+                }
+                Ok(Ack::DbError) => Some("The server could not open the vault".to_string()),
+                Ok(_) => Some("The server gave an unexpected answer".to_string()),
+                Err(e) => Some(e.to_string()),
+            },
+            Err(e) => Some(e.to_string()),
+        };
+        self.login_error = error;
+        if self.login_error.is_some() {
+            self.page.login_textarea.set_style(self.page.theme.error);
+            return;
+        }
+        self.login_backoff = Backoff::default();
+        self.status = Status::List;
+        self.page.login_textarea.set_style(self.page.theme.success);
+        self.purge_expired_trash().await;
+        self.sync().await;
+        self.subscribe().await;
+    }
+
+    /// The profile whose vault is shown.
+    pub fn current_profile(&self) -> &Profile {
+        &self.profiles[self.profile]
+    }
+
+    /// The name of the shown profile, if there are several to tell apart.
+    pub fn profile_name(&self) -> Option<&str> {
+        (self.profiles.len() > 1).then(|| self.current_profile().name.as_str())
+    }
+
+    /// Switch to the profile `step` places away, wrapping around.
+    pub async fn cycle_profile(&mut self, step: isize) {
+        let count = self.profiles.len() as isize;
+        let index = (self.profile as isize + step).rem_euclid(count) as usize;
+        self.switch_profile(index).await;
+    }
+
+    /// Show the vault of the profile at `index`, keeping the current one's
+    /// session to come back to. A profile not unlocked yet asks for its password.
+    pub async fn switch_profile(&mut self, index: usize) {
+        if index == self.profile || index >= self.profiles.len() {
+            return;
+        }
+        if let Some(subscription) = self.subscription.take() {
+            subscription.abort();
+        }
+        // Passwords are not kept for vaults out of sight.
+        self.secrets.clear();
+        let parked = Session {
+            unlocked: !matches!(self.status, Status::Login),
+            account_list: std::mem::take(&mut self.account_list),
+            trash: std::mem::take(&mut self.trash),
+            folder: self.folder.take(),
+            recent: std::mem::take(&mut self.recent),
+            conn: self.conn.take(),
+            legacy_server: std::mem::take(&mut self.legacy_server),
+            lazy_passwords: std::mem::take(&mut self.lazy_passwords),
+            login_backoff: std::mem::take(&mut self.login_backoff),
+        };
+        self.sessions.insert(self.profile, parked);
+
+        self.profile = index;
+        let session = self.sessions.remove(&index).unwrap_or_default();
+        self.account_list = session.account_list;
+        self.trash = session.trash;
+        self.folder = session.folder;
+        self.recent = session.recent;
+        self.conn = session.conn;
+        self.legacy_server = session.legacy_server;
+        self.lazy_passwords = session.lazy_passwords;
+        self.login_backoff = session.login_backoff;
+        self.login_error = None;
+        self.toast = None;
+        self.visual_anchor = None;
+        self.trash_choice = 0;
+        self.page.login_textarea.select_all();
+        self.page.login_textarea.delete_char();
+        self.page.login_textarea.set_style(self.page.theme.text);
+        self.page
+            .login_textarea
+            .set_placeholder_text("Enter your password");
+        if session.unlocked {
+            self.status = Status::List;
+            // Changes made while away were not pushed to us.
+            self.sync().await;
+            self.subscribe().await;
+        } else {
+            self.status = Status::Login;
+        }
+        self.request_render();
+    }
+
+    /// What narrows the list down: the search box and the chosen folder.
+    pub fn list_filter(&self) -> ListFilter {
+        ListFilter {
+            search: self.page.search_text(),
+            folder: self.folder.clone(),
+            recent: self.recent,
+        }
+    }
+
+    /// Indexes into the account list of the items currently shown.
+    pub fn visible_items(&self) -> Vec<usize> {
+        self.account_list.visible(&self.list_filter())
+    }
+
+    /// The selected item, if it is shown.
+    pub fn selected_item(&self) -> Option<&AccountListItem> {
+        let selected = self.account_list.selected;
+        let item = self.account_list.list.get(selected)?;
+        self.list_filter().matches(item).then_some(item)
+    }
+
+    /// Select the item at `index` of the account list, scrolling its note back to the top.
+    pub fn list_select(&mut self, index: usize) {
+        self.account_list.selected = index;
+        self.note_scroll = 0;
+    }
+
+    /// Move the selection to the first shown item if the filter hid it.
+    pub fn keep_selection_visible(&mut self) {
+        let visible = self.visible_items();
+        if !visible.contains(&self.account_list.selected) {
+            if let Some(first) = visible.first() {
+                self.list_select(*first);
             }
-            _ => {
-                self.page
-                    .account_textarea
-                    .set_cursor_style(Style::default().bg(Color::White));
-                Focus::Account
+        }
+    }
+
+    pub fn list_select_first_item(&mut self) {
+        if let Some(first) = self.visible_items().first() {
+            self.list_select(*first);
+        }
+    }
+
+    pub fn list_select_last_item(&mut self) {
+        if let Some(last) = self.visible_items().last() {
+            self.list_select(*last);
+        }
+    }
+
+    pub fn list_select_next_item(&mut self) {
+        let visible = self.visible_items();
+        if visible.is_empty() {
+            return;
+        }
+        let next = match visible
+            .iter()
+            .position(|i| *i == self.account_list.selected)
+        {
+            Some(position) => (position + 1) % visible.len(),
+            None => 0,
+        };
+        self.list_select(visible[next]);
+    }
+
+    pub fn list_select_before_item(&mut self) {
+        let visible = self.visible_items();
+        if visible.is_empty() {
+            return;
+        }
+        let before = match visible
+            .iter()
+            .position(|i| *i == self.account_list.selected)
+        {
+            Some(0) | None => visible.len() - 1,
+            Some(position) => position - 1,
+        };
+        self.list_select(visible[before]);
+    }
+
+    /// Narrow the list to the next folder of the tree, after the last one back to all items.
+    pub fn next_folder(&mut self) {
+        let folders = self.account_list.folders();
+        self.folder = match &self.folder {
+            None => folders.first().cloned(),
+            Some(current) => folders
+                .iter()
+                .position(|f| f == current)
+                .and_then(|i| folders.get(i + 1))
+                .cloned(),
+        };
+        self.keep_selection_visible();
+    }
+
+    pub fn previous_folder(&mut self) {
+        let folders = self.account_list.folders();
+        self.folder = match &self.folder {
+            None => folders.last().cloned(),
+            Some(current) => match folders.iter().position(|f| f == current) {
+                Some(0) | None => None,
+                Some(i) => Some(folders[i - 1].clone()),
+            },
+        };
+        self.keep_selection_visible();
+    }
+
+    pub fn set_folder(&mut self, folder: Option<String>) {
+        self.folder = folder;
+        self.keep_selection_visible();
+    }
+
+    pub fn scroll_note(&mut self, rows: i16) {
+        self.note_scroll = self.note_scroll.saturating_add_signed(rows);
+    }
+
+    pub fn focus_next(&mut self) {
+        let fields = Focus::edit_fields(self.page.item_type, self.page.custom_fields.len());
+        let next = match fields.iter().position(|f| *f == self.focus) {
+            Some(i) if i + 1 < fields.len() => fields[i + 1],
+            _ => fields[0],
+        };
+        self.set_focus(next);
+    }
+
+    /// Move the edit form focus, showing the cursor only in the focused text area.
+    pub fn set_focus(&mut self, focus: Focus) {
+        for field in Focus::edit_fields(self.page.item_type, self.page.custom_fields.len()) {
+            if let Some(textarea) = self.edit_textarea(field) {
+                textarea.set_cursor_style(Style::default());
             }
         }
+        let cursor = self.page.theme.cursor;
+        if let Some(textarea) = self.edit_textarea(focus) {
+            textarea.set_cursor_style(cursor);
+        }
+        self.focus = focus;
+    }
+
+    /// The edit form text area behind `focus`.
+    pub fn edit_textarea(&mut self, focus: Focus) -> Option<&mut TextArea<'static>> {
+        match focus {
+            Focus::Account => Some(&mut self.page.account_textarea),
+            Focus::Name => Some(&mut self.page.site_name_textarea),
+            Focus::Password => Some(&mut self.page.password_textarea),
+            Focus::Url => Some(&mut self.page.site_url_textarea),
+            Focus::Note => Some(&mut self.page.note_textarea),
+            Focus::Folder => Some(&mut self.page.folder_textarea),
+            Focus::Tags => Some(&mut self.page.tags_textarea),
+            Focus::CustomName(i) => self.page.custom_fields.get_mut(i).map(|f| &mut f.name),
+            Focus::CustomValue(i) => self.page.custom_fields.get_mut(i).map(|f| &mut f.value),
+            Focus::TypeField(i) => self.page.type_field_textareas.get_mut(i),
+            Focus::List => None,
+        }
     }
 
     pub fn new_item(&mut self) {
-        self.page
-            .account_textarea
-            .set_cursor_style(Style::default());
-        self.page
-            .site_name_textarea
-            .set_cursor_style(Style::default());
-        self.page
-            .site_url_textarea
-            .set_cursor_style(Style::default());
-        self.page
-            .password_textarea
-            .set_cursor_style(Style::default());
-        self.page.note_textarea.set_cursor_style(Style::default());
+        self.fill_form(&FormValues::default());
 
         self.is_edit_no_new_item = false;
         self.status = Status::Edit;
         self.focus_next();
     }
 
-    pub async fn quit_edit(&mut self) {
-        self.focus = Focus::List;
-        self.status = Status::List;
+    /// What is currently typed into the edit form.
+    pub fn form_values(&self) -> FormValues {
+        let text = |textarea: &TextArea| textarea.lines().join("\n");
+        FormValues {
+            account: text(&self.page.account_textarea),
+            password: text(&self.page.password_textarea),
+            site_name: text(&self.page.site_name_textarea),
+            site_url: text(&self.page.site_url_textarea),
+            note: text(&self.page.note_textarea),
+            tags: text(&self.page.tags_textarea),
+            folder: text(&self.page.folder_textarea),
+            item_type: self.page.item_type,
+            type_fields: self.page.type_field_textareas.iter().map(text).collect(),
+            custom_fields: self
+                .page
+                .custom_fields
+                .iter()
+                .map(|field| CustomField {
+                    name: text(&field.name),
+                    value: text(&field.value),
+                    kind: field.kind,
+                })
+                .collect(),
+        }
+    }
 
-        let password = self
-            .page
-            .password_textarea
-            .lines()
-            .first()
-            .unwrap_or(&"".to_string())
-            .clone();
-        let account = self
-            .page
-            .account_textarea
-            .lines()
-            .first()
-            .unwrap_or(&"".to_string())
-            .clone();
-        let site_name = self
-            .page
-            .site_name_textarea
-            .lines()
-            .first()
-            .unwrap_or(&"".to_string())
-            .clone();
-        let site_url = self
-            .page
-            .site_url_textarea
-            .lines()
-            .first()
-            .unwrap_or(&"".to_string())
-            .clone();
-
-        let notes = self.page.note_textarea.lines();
-        let mut note = String::new();
-        note += &notes.first().unwrap().clone();
-        for line in notes.iter().skip(1) {
-            note = note + "\n" + line;
-        }
-
-        let action = if self.is_edit_no_new_item {
-            let selected = self.account_list.selected;
-            let id = self.account_list.list[selected].id;
-            Action::ChangeWebsiteAccount {
-                id: id.unwrap(),
-                new_account: account,
-                new_password: password,
-                new_site_name: Some(site_name),
-                new_site_url: site_url,
-                new_note: Some(note),
-            }
-        } else {
-            Action::AddWebsiteAccount {
-                account,
-                password,
-                site_url,
-                site_name: Some(site_name),
-                note: Some(note),
-            }
-        };
+    /// Has the edit form been changed since it was opened?
+    pub fn is_form_dirty(&self) -> bool {
+        self.form_values() != self.form_original
+    }
 
-        let conn = crate::tcp::Connect::new().await;
-        let _ = conn.request(action).await;
+    /// Send the form to the server if anything changed, then close it.
+    ///
+    /// An invalid form stays open with the problems shown under their fields,
+    /// and the focus on the first of them.
+    pub async fn save_edit(&mut self) {
+        if self.is_form_dirty() {
+            let values = match self.form_values().validate() {
+                Ok(values) => values,
+                Err(errors) => {
+                    self.set_focus(errors[0].field);
+                    self.form_errors = errors;
+                    return;
+                }
+            };
+            let tags = values.tag_list();
+            let folder = values.folder_path();
+            let uris = values.uri_list();
+            // Validation made sure a login has a first URL and that it is a real one.
+            let site_url = uris.first().map(|uri| uri.uri.clone()).unwrap_or_default();
+            let type_fields = values.type_field_map();
+            let action = if let Some(id) = self.editing_id {
+                Action::ChangeWebsiteAccount {
+                    id,
+                    new_account: values.account,
+                    new_password: values.password,
+                    new_site_name: Some(values.site_name),
+                    new_site_url: site_url,
+                    new_note: Some(values.note),
+                    new_tags: tags,
+                    new_folder: folder,
+                    new_custom_fields: values.custom_fields,
+                    new_uris: uris,
+                    new_item_type: values.item_type,
+                    new_type_fields: type_fields,
+                }
+            } else {
+                Action::AddWebsiteAccount {
+                    account: values.account,
+                    password: values.password,
+                    site_url,
+                    site_name: Some(values.site_name),
+                    note: Some(values.note),
+                    tags,
+                    folder,
+                    custom_fields: values.custom_fields,
+                    uris,
+                    item_type: values.item_type,
+                    type_fields,
+                }
+            };
 
-        self.sync().await;
+            let Some(conn) = self.connect().await else {
+                return;
+            };
+            // The form stays open with what was typed until the server has it.
+            let error = match conn.request(action).await {
+                Ok(Ack::Ack) => None,
+                Ok(Ack::IdentityError) => Some("the vault is locked".to_string()),
+                Ok(Ack::DbError) => Some("the server could not write the vault".to_string()),
+                Ok(_) => Some("the server gave an unexpected answer".to_string()),
+                Err(e) => Some(e.to_string()),
+            };
+            if let Some(error) = error {
+                self.show_toast(format!("Not saved: {}", error), Vec::new());
+                return;
+            }
 
-        self.page.password_textarea.select_all();
-        self.page.password_textarea.delete_char();
+            self.sync().await;
+        }
+        self.close_edit();
+    }
 
-        self.page.account_textarea.select_all();
-        self.page.account_textarea.delete_char();
+    /// Leave the edit form, asking first if there are changes to lose.
+    pub fn cancel_edit(&mut self) {
+        if self.is_form_dirty() {
+            self.status = Status::Discard;
+        } else {
+            self.close_edit();
+        }
+    }
 
-        self.page.site_name_textarea.select_all();
-        self.page.site_name_textarea.delete_char();
+    pub fn discard_edit(&mut self) {
+        self.close_edit();
+    }
 
-        self.page.site_url_textarea.select_all();
-        self.page.site_url_textarea.delete_char();
+    /// Back from the discard dialog to the form.
+    pub fn keep_editing(&mut self) {
+        self.status = Status::Edit;
+    }
 
-        self.page.note_textarea.select_all();
-        self.page.note_textarea.delete_char();
+    fn close_edit(&mut self) {
+        self.form_errors.clear();
+        self.editing_id = None;
+        self.edit_warning = None;
+        self.set_focus(Focus::List);
+        self.status = Status::List;
+        self.fill_form(&FormValues::default());
     }
 
-    pub fn edit(&mut self) {
-        self.page
-            .account_textarea
-            .set_cursor_style(Style::default());
-        self.page
-            .site_name_textarea
-            .set_cursor_style(Style::default());
-        self.page
-            .site_url_textarea
-            .set_cursor_style(Style::default());
+    /// Replace the content of the edit form with `values`, which become the
+    /// baseline for [`App::is_form_dirty`].
+    fn fill_form(&mut self, values: &FormValues) {
+        let fields = [
+            (&mut self.page.account_textarea, &values.account),
+            (&mut self.page.password_textarea, &values.password),
+            (&mut self.page.site_name_textarea, &values.site_name),
+            (&mut self.page.site_url_textarea, &values.site_url),
+            (&mut self.page.note_textarea, &values.note),
+            (&mut self.page.tags_textarea, &values.tags),
+            (&mut self.page.folder_textarea, &values.folder),
+        ];
+        for (textarea, value) in fields {
+            textarea.select_all();
+            textarea.delete_char();
+            textarea.insert_str(value);
+        }
         self.page
-            .password_textarea
-            .set_cursor_style(Style::default());
-        self.page.note_textarea.set_cursor_style(Style::default());
-
-        self.is_edit_no_new_item = true;
+            .set_item_type(values.item_type, &values.type_fields);
+        self.page.custom_fields = values
+            .custom_fields
+            .iter()
+            .map(|field| self.page.custom_field_input(field))
+            .collect();
+        // The form as opened holds the secrets of the item in clear text.
+        self.form_original.zeroize();
+        self.form_original = values.clone();
+    }
 
-        let selected = self.account_list.selected;
-        self.page
-            .account_textarea
-            .insert_str(self.account_list.list[selected].account.clone());
-        self.page
-            .password_textarea
-            .insert_str(self.account_list.list[selected].password.clone());
-        self.page
-            .site_url_textarea
-            .insert_str(self.account_list.list[selected].site_url.clone());
-        self.page.site_name_textarea.insert_str(
-            self.account_list.list[selected]
-                .site_name
-                .clone()
-                .unwrap_or("".to_string()),
-        );
-        self.page.note_textarea.insert_str(
-            self.account_list.list[selected]
-                .note
-                .clone()
-                .unwrap_or("".to_string()),
-        );
+    pub async fn edit(&mut self) {
+        if self.selected_item().is_none() {
+            return;
+        }
+        let Some(item) = self.item_with_secrets(self.account_list.selected).await else {
+            return;
+        };
+        let values = Zeroizing::new(FormValues::from_item(&item));
+        self.editing_id = item.id;
+        self.fill_form(&values);
 
         self.is_edit_no_new_item = true;
         self.status = Status::Edit;
@@ -355,25 +1018,290 @@ impl App {
     }
 
     pub fn edit_input(&mut self, key: KeyEvent) {
-        let textarea = match self.focus {
-            Focus::Account => &mut self.page.account_textarea,
-            Focus::Name => &mut self.page.site_name_textarea,
-            Focus::Password => &mut self.page.password_textarea,
-            Focus::Url => &mut self.page.site_url_textarea,
-            Focus::Note => &mut self.page.note_textarea,
-            _ => &mut self.page.account_textarea,
+        if let Some(textarea) = self.edit_textarea(self.focus) {
+            textarea.input(key);
+        }
+        self.revalidate_form();
+    }
+
+    /// Switch a new item to the next type. The type of a saved item stays.
+    pub fn cycle_item_type(&mut self) {
+        if self.is_edit_no_new_item {
+            return;
+        }
+        let item_type = self.page.item_type.next();
+        self.page.set_item_type(item_type, &[]);
+        let first = Focus::edit_fields(item_type, 0)[0];
+        self.set_focus(first);
+        self.revalidate_form();
+    }
+
+    /// Index of the custom field the focus is in, if any.
+    fn focused_custom_field(&self) -> Option<usize> {
+        match self.focus {
+            Focus::CustomName(i) | Focus::CustomValue(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// Append an empty custom field to the form and focus its name.
+    pub fn add_custom_field(&mut self) {
+        let input = self.page.custom_field_input(&CustomField::default());
+        self.page.custom_fields.push(input);
+        self.set_focus(Focus::CustomName(self.page.custom_fields.len() - 1));
+        self.revalidate_form();
+    }
+
+    /// Remove the focused custom field.
+    pub fn remove_custom_field(&mut self) {
+        let Some(i) = self.focused_custom_field() else {
+            return;
+        };
+        self.page.custom_fields.remove(i);
+        let len = self.page.custom_fields.len();
+        let focus = if len == 0 {
+            Focus::Note
+        } else {
+            Focus::CustomName(i.min(len - 1))
+        };
+        self.set_focus(focus);
+        self.revalidate_form();
+    }
+
+    /// Swap the focused custom field with its neighbour, `-1` above or `1` below.
+    pub fn move_custom_field(&mut self, offset: isize) {
+        let Some(i) = self.focused_custom_field() else {
+            return;
+        };
+        let Some(j) = i
+            .checked_add_signed(offset)
+            .filter(|j| *j < self.page.custom_fields.len())
+        else {
+            return;
+        };
+        self.page.custom_fields.swap(i, j);
+        let focus = match self.focus {
+            Focus::CustomValue(_) => Focus::CustomValue(j),
+            _ => Focus::CustomName(j),
+        };
+        self.set_focus(focus);
+        self.revalidate_form();
+    }
+
+    /// Switch the focused custom field to the next kind.
+    pub fn cycle_custom_field_kind(&mut self) {
+        let Some(i) = self.focused_custom_field() else {
+            return;
+        };
+        let theme = self.page.theme.clone();
+        let field = &mut self.page.custom_fields[i];
+        field.set_kind(field.kind.next(), &theme);
+        self.revalidate_form();
+    }
+
+    /// Once errors are shown, keep them up to date while the user fixes them.
+    pub fn revalidate_form(&mut self) {
+        if !self.form_errors.is_empty() {
+            self.form_errors = self.form_values().validate().err().unwrap_or_default();
+        }
+    }
+
+    /// Open the selected item in the browser, asking which URL first if it has several.
+    pub fn visit(&mut self) {
+        let Some(item) = self.selected_item() else {
+            return;
         };
-        textarea.input(key);
+        if item.site_url.is_empty() {
+            return;
+        }
+        let uris = item.visitable_uris();
+        let url = match uris.len() {
+            0 => item.site_url.clone(),
+            1 => uris[0].uri.clone(),
+            _ => {
+                self.url_choice = 0;
+                self.status = Status::PickUrl;
+                return;
+            }
+        };
+        if self.open_url(&url) {
+            self.mark_used();
+        }
     }
 
-    pub fn visit(&self) {
+    /// Copy the password of the selected item to the clipboard.
+    pub async fn copy_password(&mut self) {
+        if self.selected_item().is_none() {
+            return;
+        }
+        let Some(item) = self.item_with_secrets(self.account_list.selected).await else {
+            return;
+        };
+        if item.secret().is_empty() {
+            return;
+        }
+        if crate::clipboard::copy(item.secret()).is_ok() {
+            self.mark_used();
+        }
+    }
+
+    /// Record that the selected item was just used, here and on the server.
+    fn mark_used(&mut self) {
         let selected = self.account_list.selected;
-        let url = self.account_list.list[selected].site_url.clone();
-        if webbrowser::open(&url).is_err() {
-            println!("Failed to open the url");
+        let Some(item) = self.account_list.list.get_mut(selected) else {
+            return;
+        };
+        item.last_used_at = Some(Utc::now());
+        let Some(id) = item.id else {
+            return;
+        };
+        // Nothing waits for this, so it does not hold up the interface.
+        let conn = self.conn.clone();
+        let profile = self.current_profile().clone();
+        let legacy_server = self.legacy_server;
+        tokio::spawn(async move {
+            // The error is not `Send`, so it must not live across the request.
+            let conn = match conn {
+                Some(conn) => Some(conn),
+                None if legacy_server => Connect::legacy(&profile).await.ok().map(Arc::new),
+                None => Connect::new(&profile).await.ok().map(Arc::new),
+            };
+            if let Some(conn) = conn {
+                if conn.supports(Capability::Timestamps) {
+                    let _ = conn.request(Action::TouchWebsiteAccount { id }).await;
+                }
+            }
+        });
+    }
+
+    /// Switch between every item and the recently used ones.
+    pub fn toggle_recent(&mut self) {
+        self.recent = !self.recent;
+        self.keep_selection_visible();
+    }
+
+    pub fn pick_url_next(&mut self) {
+        let len = self.selected_item().map_or(0, |i| i.visitable_uris().len());
+        if len > 0 {
+            self.url_choice = (self.url_choice + 1) % len;
+        }
+    }
+
+    pub fn pick_url_previous(&mut self) {
+        let len = self.selected_item().map_or(0, |i| i.visitable_uris().len());
+        if len > 0 {
+            self.url_choice = (self.url_choice + len - 1) % len;
+        }
+    }
+
+    pub fn open_picked_url(&mut self) {
+        if let Some(url) = self.selected_item().and_then(|i| {
+            i.visitable_uris()
+                .get(self.url_choice)
+                .map(|u| u.uri.clone())
+        }) {
+            if self.open_url(&url) {
+                self.mark_used();
+            }
+        }
+        self.status = Status::List;
+    }
+
+    /// Open `url` in the browser, telling in a toast if that failed.
+    fn open_url(&mut self, url: &str) -> bool {
+        let opened = webbrowser::open(url).is_ok();
+        if !opened {
+            self.show_toast(format!("Could not open {}", url), Vec::new());
+        }
+        opened
+    }
+
+    pub fn cancel_pick_url(&mut self) {
+        self.status = Status::List;
+    }
+
+    /// Open the password history of the selected item, if it has one.
+    pub async fn show_history(&mut self) {
+        if self.selected_item().is_none() {
+            return;
+        }
+        let Some(item) = self.item_with_secrets(self.account_list.selected).await else {
+            return;
+        };
+        if item.password_history.is_empty() {
+            return;
+        }
+        self.history_item = Some(item);
+        self.history_choice = 0;
+        self.reveal_history = false;
+        self.status = Status::History;
+    }
+
+    /// The length of the password history being browsed.
+    fn history_len(&self) -> usize {
+        self.history_item
+            .as_ref()
+            .map_or(0, |item| item.password_history.len())
+    }
+
+    pub fn history_next(&mut self) {
+        let len = self.history_len();
+        if len > 0 {
+            self.history_choice = (self.history_choice + 1) % len;
+        }
+    }
+
+    pub fn history_previous(&mut self) {
+        let len = self.history_len();
+        if len > 0 {
+            self.history_choice = (self.history_choice + len - 1) % len;
+        }
+    }
+
+    pub fn toggle_history_passwords(&mut self) {
+        self.reveal_history = !self.reveal_history;
+    }
+
+    /// Make the highlighted old password the current one again.
+    ///
+    /// The server keeps the replaced password in the history, so this can be undone
+    /// the same way.
+    pub async fn restore_password(&mut self) {
+        let Some(item) = self.history_item.as_ref() else {
+            return;
+        };
+        let Some(old) = item.password_history.get(self.history_choice) else {
+            return;
+        };
+        let mut item = item.clone();
+        item.password.clone_from(&old.password);
+        let Some(action) = item.change_action() else {
+            return;
+        };
+
+        let Some(conn) = self.connect().await else {
+            return;
+        };
+        if conn.request(action).await.is_ok() {
+            self.sync().await;
         }
+        self.close_history();
     }
-    pub fn try_delete(&mut self) {
+
+    pub fn close_history(&mut self) {
+        self.history_item = None;
+        self.reveal_history = false;
+        self.status = Status::List;
+    }
+
+    pub async fn try_delete(&mut self) {
+        if self.targets().is_empty() {
+            return;
+        }
+        let Some(conn) = self.connect().await else {
+            return;
+        };
+        self.delete_to_trash = conn.supports(Capability::Trash);
         self.status = Status::Delete;
         // self.delete = true;
     }
@@ -383,20 +1311,501 @@ impl App {
         // self.delete = false;
     }
 
+    /// Move the marked items, or the selected one, to the trash, offering to
+    /// undo it for a while.
     pub async fn delete(&mut self) {
-        let selected = self.account_list.selected;
-        let id = self.account_list.list[selected].id.unwrap();
-        let action = Action::DeleteWebsiteAccount { website_id: id };
+        let targets = self.targets();
+        let title = match targets.as_slice() {
+            [one] => self.account_list.list[*one].title(),
+            many => format!("{} items", many.len()),
+        };
 
-        let conn = crate::tcp::Connect::new().await;
-        let ack = conn.request(action).await;
-        if ack.is_ok() {
+        let Some(conn) = self.connect().await else {
+            return self.cancel_delete();
+        };
+        let mut deleted = Vec::new();
+        for id in targets.iter().filter_map(|i| self.account_list.list[*i].id) {
+            let action = Action::DeleteWebsiteAccount { website_id: id };
+            if conn.request(action).await.is_ok() {
+                deleted.push(id);
+            }
+        }
+        if !deleted.is_empty() {
+            if conn.supports(Capability::Trash) {
+                self.show_toast(format!("Moved {} to the trash", title), deleted);
+            } else {
+                self.show_toast(format!("Deleted {}", title), Vec::new());
+            }
+            self.clear_marks();
             self.sync().await;
         }
         // self.delete = false;
         self.cancel_delete();
     }
 
+    /// Bring back the items deleted last, while their toast is shown.
+    pub async fn undo(&mut self) {
+        let Some(ids) = self.toast.as_ref().map(|toast| toast.undo.clone()) else {
+            return;
+        };
+        if ids.is_empty() {
+            return;
+        }
+        let Some(conn) = self.connect().await else {
+            return;
+        };
+        for id in &ids {
+            let _ = conn
+                .request(Action::RestoreWebsiteAccount { id: *id })
+                .await;
+        }
+        self.show_toast("Restored", Vec::new());
+        self.sync().await;
+        if let Some(i) = self
+            .account_list
+            .list
+            .iter()
+            .position(|i| i.id == Some(ids[0]))
+        {
+            self.list_select(i);
+        }
+    }
+
+    /// Indexes of the items a bulk operation works on: the marked ones,
+    /// including a visual range being marked, or else the selected one.
+    ///
+    /// Marked items hidden by the search or folder are left alone.
+    pub fn targets(&self) -> Vec<usize> {
+        let marked = self.marked_items();
+        if !marked.is_empty() {
+            let visible = self.visible_items();
+            return marked.into_iter().filter(|i| visible.contains(i)).collect();
+        }
+        match self.selected_item() {
+            Some(_) => vec![self.account_list.selected],
+            None => Vec::new(),
+        }
+    }
+
+    /// Indexes of the marked items, including a visual range being marked.
+    pub fn marked_items(&self) -> Vec<usize> {
+        let range = self.visual_range();
+        self.account_list
+            .list
+            .iter()
+            .enumerate()
+            .filter(|(i, item)| {
+                range.contains(i)
+                    || item
+                        .id
+                        .is_some_and(|id| self.account_list.marked.contains(&id))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// The shown items between the visual anchor and the selection.
+    fn visual_range(&self) -> Vec<usize> {
+        let Some(anchor) = self.visual_anchor else {
+            return Vec::new();
+        };
+        let visible = self.visible_items();
+        let position = |index: usize| visible.iter().position(|i| *i == index);
+        match (position(anchor), position(self.account_list.selected)) {
+            (Some(a), Some(b)) => visible[a.min(b)..=a.max(b)].to_vec(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Mark or unmark the selected item.
+    pub fn toggle_mark(&mut self) {
+        if self.selected_item().is_some() {
+            self.account_list.toggle_mark(self.account_list.selected);
+        }
+    }
+
+    /// Start marking the range from the selection to where it moves, or mark
+    /// that range and stop.
+    pub fn toggle_visual(&mut self) {
+        if self.visual_anchor.is_some() {
+            let range = self.visual_range();
+            let ids = range.iter().filter_map(|i| self.account_list.list[*i].id);
+            self.account_list.marked.extend(ids);
+            self.visual_anchor = None;
+        } else if self.selected_item().is_some() {
+            self.visual_anchor = Some(self.account_list.selected);
+        }
+    }
+
+    /// Mark every item matching the search and folder, or unmark them if they
+    /// all are.
+    pub fn mark_all_visible(&mut self) {
+        let visible = self.visible_items();
+        self.account_list.toggle_marks(&visible);
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.account_list.marked.clear();
+        self.visual_anchor = None;
+    }
+
+    /// Ask for the tags to add to or remove from the marked items.
+    pub fn try_bulk_tag(&mut self) {
+        if self.targets().is_empty() {
+            return;
+        }
+        self.page.bulk_tag_textarea.select_all();
+        self.page.bulk_tag_textarea.delete_char();
+        self.status = Status::BulkTag;
+    }
+
+    pub fn bulk_tag_input(&mut self, key: KeyEvent) {
+        self.page.bulk_tag_textarea.input(key);
+    }
+
+    pub fn cancel_bulk_tag(&mut self) {
+        self.status = Status::List;
+    }
+
+    /// Add the typed tags to the marked items and remove the ones typed as `-tag`.
+    pub async fn bulk_tag(&mut self) {
+        let input = self.page.bulk_tag_textarea.lines().join(" ");
+        let (remove, add): (Vec<&str>, Vec<&str>) = input
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|tag| !tag.is_empty() && *tag != "-")
+            .partition(|tag| tag.starts_with('-'));
+        let remove: Vec<&str> = remove.iter().map(|tag| &tag[1..]).collect();
+
+        let Some(conn) = self.connect().await else {
+            return;
+        };
+        let mut changed = 0;
+        for i in self.targets() {
+            // The change sends the whole item back, password included.
+            let Some(mut item) = self.item_with_secrets(i).await else {
+                continue;
+            };
+            let old_tags = item.tags.clone();
+            item.tags
+                .retain(|tag| !remove.iter().any(|r| r.eq_ignore_ascii_case(tag)));
+            for tag in &add {
+                if !item.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                    item.tags.push(tag.to_string());
+                }
+            }
+            if item.tags == old_tags {
+                continue;
+            }
+            let Some(action) = item.change_action() else {
+                continue;
+            };
+            if conn.request(action).await.is_ok() {
+                changed += 1;
+            }
+        }
+        self.show_toast(format!("Changed the tags of {} items", changed), Vec::new());
+        self.sync().await;
+        self.status = Status::List;
+    }
+
+    /// Write the marked items to a CSV file.
+    pub async fn export_marked(&mut self) {
+        let targets = self.targets();
+        if targets.is_empty() {
+            return;
+        }
+        let mut items = Vec::new();
+        for i in targets {
+            let Some(item) = self.item_with_secrets(i).await else {
+                let title = self.account_list.list[i].title();
+                self.show_toast(
+                    format!("Export failed: could not fetch the secrets of {}", title),
+                    Vec::new(),
+                );
+                return;
+            };
+            items.push(item);
+        }
+        let items: Vec<&AccountListItem> = items.iter().map(|item| &**item).collect();
+        let message = match crate::export::write_csv(&items) {
+            Ok(path) => format!("Exported {} items to {}", items.len(), path.display()),
+            Err(e) => format!("Export failed: {}", e),
+        };
+        self.show_toast(message, Vec::new());
+    }
+
+    /// Check whether the sites of the marked items are still up.
+    pub async fn check_dead_links(&mut self) {
+        let ids: Vec<i32> = self
+            .targets()
+            .iter()
+            .filter_map(|i| self.account_list.list[*i].id)
+            .collect();
+        if ids.is_empty() {
+            return;
+        }
+        let Some(conn) = self.connect().await else {
+            return;
+        };
+        // Older servers check every account; their answer covers the marked ones too.
+        let ids = if conn.supports(Capability::DeadLinkIds) {
+            ids
+        } else {
+            Vec::new()
+        };
+        let action = Action::CheckDeadLink { ids };
+        // On a pipelined connection the other requests go on meanwhile.
+        if let (true, Some(events)) = (conn.is_pipelined(), self.events.clone()) {
+            self.show_toast("Checking the links…", Vec::new());
+            let profile = self.profile;
+            tokio::spawn(async move {
+                let list = match conn.request_with_timeout(action, DEAD_LINK_TIMEOUT).await {
+                    Ok(Ack::DeadLink { list }) => Some(list),
+                    _ => None,
+                };
+                let _ = events.send(Event::DeadLinksChecked(profile, list));
+            });
+            return;
+        }
+        let list = match conn.request_with_timeout(action, DEAD_LINK_TIMEOUT).await {
+            Ok(Ack::DeadLink { list }) => Some(list),
+            _ => None,
+        };
+        self.dead_links_checked(self.profile, list);
+    }
+
+    /// Show what the server of `profile` found checking the links, `None` if
+    /// it failed. The vault may have been switched away from meanwhile.
+    pub fn dead_links_checked(&mut self, profile: usize, list: Option<Vec<(i32, bool)>>) {
+        if profile != self.profile {
+            if let (Some(session), Some(list)) = (self.sessions.get_mut(&profile), list) {
+                session.account_list.set_dead(&list);
+            }
+            return;
+        }
+        let Some(list) = list else {
+            self.show_toast("Checking the links failed", Vec::new());
+            return;
+        };
+        self.account_list.set_dead(&list);
+        let dead = list.iter().filter(|(_, is_dead)| *is_dead).count();
+        self.show_toast(
+            format!("Checked {} links, {} dead", list.len(), dead),
+            Vec::new(),
+        );
+    }
+
+    /// Fetch the deleted items and open the trash.
+    pub async fn show_trash(&mut self) {
+        let Some(conn) = self.connect().await else {
+            return;
+        };
+        if !conn.supports(Capability::Trash) {
+            self.show_toast("The server has no trash", Vec::new());
+            return;
+        }
+        self.fetch_trash().await;
+        self.trash_choice = 0;
+        self.status = Status::Trash;
+    }
+
+    async fn fetch_trash(&mut self) {
+        let Some(conn) = self.connect().await else {
+            return;
+        };
+        if let Ok(Ack::Info { mut list }) = conn.request(Action::GetTrash).await {
+            // Most recently deleted first.
+            list.sort_by_key(|item| std::cmp::Reverse(item.deleted_at));
+            self.trash = list;
+        }
+        self.trash_choice = self.trash_choice.min(self.trash.len().saturating_sub(1));
+    }
+
+    /// The highlighted trash item.
+    pub fn selected_trash_item(&self) -> Option<&AccountListItem> {
+        self.trash.get(self.trash_choice)
+    }
+
+    pub fn trash_next(&mut self) {
+        if !self.trash.is_empty() {
+            self.trash_choice = (self.trash_choice + 1) % self.trash.len();
+        }
+    }
+
+    pub fn trash_previous(&mut self) {
+        if !self.trash.is_empty() {
+            let len = self.trash.len();
+            self.trash_choice = (self.trash_choice + len - 1) % len;
+        }
+    }
+
+    /// Take the highlighted item out of the trash.
+    pub async fn restore_from_trash(&mut self) {
+        let Some(id) = self.selected_trash_item().and_then(|i| i.id) else {
+            return;
+        };
+        let Some(conn) = self.connect().await else {
+            return;
+        };
+        if let Ok(Ack::Ack) = conn.request(Action::RestoreWebsiteAccount { id }).await {
+            self.fetch_trash().await;
+            self.sync().await;
+        }
+    }
+
+    pub fn try_purge(&mut self) {
+        if self.selected_trash_item().is_some() {
+            self.status = Status::Purge;
+        }
+    }
+
+    pub fn cancel_purge(&mut self) {
+        self.status = Status::Trash;
+    }
+
+    /// Delete the highlighted trash item for good.
+    pub async fn purge(&mut self) {
+        if let Some(id) = self.selected_trash_item().and_then(|i| i.id) {
+            let Some(conn) = self.connect().await else {
+                return self.cancel_purge();
+            };
+            if conn
+                .request(Action::PurgeWebsiteAccount { id })
+                .await
+                .is_ok()
+            {
+                self.fetch_trash().await;
+            }
+        }
+        self.cancel_purge();
+    }
+
+    pub fn close_trash(&mut self) {
+        self.status = Status::List;
+    }
+
+    /// Drop the items that have been in the trash longer than the retention.
+    async fn purge_expired_trash(&mut self) {
+        let Some(retention) = self.trash_retention else {
+            return;
+        };
+        let action = Action::PurgeTrash {
+            deleted_before: Utc::now() - retention,
+        };
+        if let Some(conn) = self.connect().await {
+            if conn.supports(Capability::Trash) {
+                let _ = conn.request(action).await;
+            }
+        }
+    }
+
+    /// Open the change master password screen with empty inputs.
+    pub fn show_master_password(&mut self) {
+        self.clear_master_inputs();
+        self.set_master_focus(MasterField::Current);
+        self.status = Status::MasterPassword;
+    }
+
+    fn clear_master_inputs(&mut self) {
+        for textarea in &mut self.page.master_textareas {
+            textarea.select_all();
+            textarea.delete_char();
+        }
+        self.master_error = None;
+    }
+
+    fn set_master_focus(&mut self, field: MasterField) {
+        let cursor = self.page.theme.cursor;
+        for (i, textarea) in self.page.master_textareas.iter_mut().enumerate() {
+            if i == field as usize {
+                textarea.set_cursor_style(cursor);
+            } else {
+                textarea.set_cursor_style(Style::default());
+            }
+        }
+        self.master_focus = field;
+    }
+
+    pub fn next_master_field(&mut self) {
+        self.set_master_focus(self.master_focus.next());
+    }
+
+    pub fn master_password_input(&mut self, key: KeyEvent) {
+        self.page.master_textareas[self.master_focus as usize].input(key);
+        self.master_error = None;
+    }
+
+    pub fn cancel_master_password(&mut self) {
+        self.clear_master_inputs();
+        self.status = Status::List;
+    }
+
+    fn master_input(&self, field: MasterField) -> Zeroizing<String> {
+        Zeroizing::new(self.page.master_textareas[field as usize].lines().join(""))
+    }
+
+    /// Change the master password on the server, then unlock with the new one.
+    pub async fn save_master_password(&mut self) {
+        let [current, new, confirm] = MasterField::ALL.map(|field| self.master_input(field));
+        if let Err((field, message)) = validate_master_password(&current, &new, &confirm) {
+            self.master_error = Some((field, message.to_string()));
+            self.set_master_focus(field);
+            return;
+        }
+        let Some(conn) = self.connect().await else {
+            return;
+        };
+        if !conn.supports(Capability::ChangeMasterPassword) {
+            self.master_error = Some((
+                MasterField::Current,
+                "The server cannot change it".to_string(),
+            ));
+            return;
+        }
+        let action = Action::ChangeMasterPassword {
+            old_password: current.to_string(),
+            new_password: new.to_string(),
+        };
+        match conn.request(action).await {
+            Ok(Ack::Ack) => {}
+            Ok(Ack::IdentityError) => {
+                self.master_error = Some((MasterField::Current, "Wrong password".to_string()));
+                self.set_master_focus(MasterField::Current);
+                return;
+            }
+            _ => {
+                self.master_error = Some((MasterField::New, "Could not be changed".to_string()));
+                return;
+            }
+        }
+
+        // The vault is re-keyed: nothing fetched or opened under the old
+        // password is used again. Passwords are only ever cached in memory.
+        self.secrets.clear();
+        self.conn = None;
+        self.cancel_master_password();
+        let unlocked = match self.connect().await {
+            Some(conn) => matches!(
+                conn.request(Action::CheckIdentity {
+                    password: new.to_string()
+                })
+                .await,
+                Ok(Ack::Ack)
+            ),
+            None => false,
+        };
+        if unlocked {
+            self.show_toast("Master password changed", Vec::new());
+        } else {
+            self.status = Status::Login;
+            self.page.login_textarea.select_all();
+            self.page.login_textarea.delete_char();
+            self.page
+                .login_textarea
+                .set_placeholder_text("Password changed, unlock with the new one");
+        }
+    }
+
     pub fn search(&mut self) {
         if let Status::Search = self.status {
             self.status = Status::List;
@@ -405,11 +1814,66 @@ impl App {
             self.status = Status::Search;
             self.page
                 .search_textarea
-                .set_cursor_style(Style::default().bg(Color::White));
+                .set_cursor_style(self.page.theme.cursor);
         }
     }
 
     pub fn search_input(&mut self, key: KeyEvent) {
         self.page.search_textarea.input(key);
+        self.keep_selection_visible();
+    }
+
+    /// Insert pasted text into the active text area.
+    ///
+    /// Only multi-line fields like the note and the URLs keep line breaks;
+    /// everywhere else a newline would end up in a value that is meant to be a
+    /// single line.
+    pub fn paste(&mut self, text: &str) {
+        let single_line = text.replace(['\r', '\n'], "");
+        match self.key_context() {
+            Context::Login => {
+                self.page.login_textarea.insert_str(single_line);
+            }
+            Context::Search => {
+                self.page.search_textarea.insert_str(single_line);
+                self.keep_selection_visible();
+            }
+            Context::BulkTag => {
+                self.page.bulk_tag_textarea.insert_str(single_line);
+            }
+            Context::MasterPassword => {
+                self.page.master_textareas[self.master_focus as usize].insert_str(single_line);
+                self.master_error = None;
+            }
+            Context::Edit => {
+                let focus = self.focus;
+                let multiline = match focus {
+                    Focus::Note | Focus::Url => true,
+                    Focus::TypeField(i) => self
+                        .page
+                        .item_type
+                        .fields()
+                        .get(i)
+                        .is_some_and(|spec| spec.multiline),
+                    _ => false,
+                };
+                let text = if multiline {
+                    text.replace("\r\n", "\n").replace('\r', "\n")
+                } else {
+                    single_line
+                };
+                if let Some(textarea) = self.edit_textarea(focus) {
+                    textarea.insert_str(text);
+                }
+                self.revalidate_form();
+            }
+            Context::List
+            | Context::Delete
+            | Context::Discard
+            | Context::PickUrl
+            | Context::History
+            | Context::Trash
+            | Context::Purge => {}
+        }
     }
 }
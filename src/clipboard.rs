@@ -0,0 +1,17 @@
+use std::io::{self, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::app::AppResult;
+
+/// Put `text` on the system clipboard with an OSC 52 escape sequence.
+///
+/// The terminal does the copying, so this also works over SSH, but terminals
+/// that do not support OSC 52 (or have it turned off) silently ignore it.
+/// It goes to stderr like the rest of the interface.
+pub fn copy(text: &str) -> AppResult<()> {
+    let mut stderr = io::stderr();
+    write!(stderr, "\x1b]52;c;{}\x07", STANDARD.encode(text))?;
+    stderr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::{app::AppResult, keymap::KeymapConfig, ui::theme::ThemeConfig};
+
+/// Environment variable overriding the config file location.
+pub const CONFIG_ENV: &str = "YSNP_CONFIG";
+
+/// Client configuration, read from `config.toml`.
+///
+/// Every section is optional; a missing file means all defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub keymap: KeymapConfig,
+    pub theme: ThemeConfig,
+    pub trash: TrashConfig,
+    /// The servers to choose from on the login screen, in order.
+    pub profiles: Vec<Profile>,
+}
+
+/// The `[trash]` section of the config file.
+///
+/// ```toml
+/// [trash]
+/// retention_days = 30
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// Deleted accounts older than this are purged when unlocking; 0 keeps them.
+    pub retention_days: u32,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self { retention_days: 30 }
+    }
+}
+
+impl TrashConfig {
+    /// How long deleted accounts are kept, `None` for ever.
+    pub fn retention(&self) -> Option<chrono::Duration> {
+        match self.retention_days {
+            0 => None,
+            days => Some(chrono::Duration::days(days.into())),
+        }
+    }
+}
+
+/// A vault server, from a `[[profiles]]` section. Each one is unlocked on its
+/// own and keeps its own session while another one is shown.
+///
+/// ```toml
+/// [[profiles]]
+/// name = "personal"
+///
+/// [[profiles]]
+/// name = "team"
+/// address = "vault.example.com:6124"
+/// transport = "tls"
+/// tls = { ca_file = "/etc/ssl/team-ca.pem" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    /// `host:port` of the server.
+    #[serde(default = "Profile::default_address")]
+    pub address: String,
+    #[serde(default)]
+    pub transport: TransportKind,
+    #[serde(default)]
+    pub tls: TlsConfig,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            address: Self::default_address(),
+            transport: TransportKind::default(),
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+impl Profile {
+    fn default_address() -> String {
+        "127.0.0.1:6123".to_string()
+    }
+
+    /// The name the server certificate must be for: `tls.server_name`, or the
+    /// host of the address.
+    pub fn server_name(&self) -> &str {
+        if let Some(name) = &self.tls.server_name {
+            return name;
+        }
+        let host = self
+            .address
+            .rsplit_once(':')
+            .map_or(self.address.as_str(), |(host, _)| host);
+        host.trim_start_matches('[').trim_end_matches(']')
+    }
+}
+
+/// How a [`Profile`] talks to its server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportKind {
+    #[default]
+    Tcp,
+    Tls,
+}
+
+/// The TLS settings of a [`Profile`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// PEM certificates to trust instead of the usual web roots.
+    pub ca_file: Option<PathBuf>,
+    /// Overrides the host of the address when checking the certificate.
+    pub server_name: Option<String>,
+}
+
+impl Config {
+    /// `$YSNP_CONFIG`, or `you_should_not_pass/config.toml` in the user config directory.
+    pub fn path() -> Option<PathBuf> {
+        match std::env::var_os(CONFIG_ENV) {
+            Some(path) => Some(PathBuf::from(path)),
+            None => {
+                dirs::config_dir().map(|dir| dir.join("you_should_not_pass").join("config.toml"))
+            }
+        }
+    }
+
+    /// Load the config file, falling back to the defaults if there is none.
+    pub fn load() -> AppResult<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(content) => Self::parse(&content)
+                .map_err(|e| format!("Invalid config {}: {}", path.display(), e).into()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn parse(content: &str) -> AppResult<Self> {
+        let config: Self = toml::from_str(content)?;
+        let mut names = std::collections::HashSet::new();
+        for profile in &config.profiles {
+            if profile.name.is_empty() {
+                return Err("A profile has no name".into());
+            }
+            if !names.insert(profile.name.as_str()) {
+                return Err(format!("Profile {} is defined twice", profile.name).into());
+            }
+        }
+        Ok(config)
+    }
+
+    /// The configured profiles, or the default local server if there are none.
+    pub fn profiles(&self) -> Vec<Profile> {
+        if self.profiles.is_empty() {
+            vec![Profile::default()]
+        } else {
+            self.profiles.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        assert_eq!(Config::default().profiles(), vec![Profile::default()]);
+
+        let config = Config::parse(
+            r#"
+            [[profiles]]
+            name = "personal"
+
+            [[profiles]]
+            name = "team"
+            address = "vault.example.com:6124"
+            transport = "tls"
+            tls = { ca_file = "/etc/ssl/team-ca.pem" }
+            "#,
+        )
+        .unwrap();
+        let profiles = config.profiles();
+        assert_eq!(profiles[0].address, "127.0.0.1:6123");
+        assert_eq!(profiles[0].transport, TransportKind::Tcp);
+        assert_eq!(profiles[1].transport, TransportKind::Tls);
+        assert_eq!(profiles[1].server_name(), "vault.example.com");
+        assert_eq!(
+            profiles[1].tls.ca_file,
+            Some(PathBuf::from("/etc/ssl/team-ca.pem"))
+        );
+
+        let twice = "[[profiles]]\nname = \"a\"\n[[profiles]]\nname = \"a\"\n";
+        assert!(Config::parse(twice).is_err());
+    }
+}
@@ -5,9 +5,10 @@ use futures::{FutureExt, StreamExt};
 use tokio::sync::mpsc;
 
 use crate::app::AppResult;
+use crate::tcp::ChangeEvent;
 
 /// Terminal events.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum Event {
     /// Terminal tick.
     Tick,
@@ -17,6 +18,13 @@ pub enum Event {
     Mouse(MouseEvent),
     /// Terminal resize.
     Resize(u16, u16),
+    /// Bracketed paste.
+    Paste(String),
+    /// The server pushed a change to the vault.
+    VaultChanged(ChangeEvent),
+    /// The links checked in the background for the profile at this index,
+    /// `None` if the check failed.
+    DeadLinksChecked(usize, Option<Vec<(i32, bool)>>),
 }
 
 /// Terminal event handler.
@@ -67,7 +75,8 @@ impl EventHandler {
                       },
                       CrosstermEvent::FocusGained => {
                       },
-                      CrosstermEvent::Paste(_) => {
+                      CrosstermEvent::Paste(text) => {
+                        _sender.send(Event::Paste(text)).unwrap();
                       },
                     }
                   }
@@ -81,6 +90,11 @@ impl EventHandler {
         }
     }
 
+    /// A sender for events that do not come from the terminal.
+    pub fn sender(&self) -> mpsc::UnboundedSender<Event> {
+        self.sender.clone()
+    }
+
     /// Receive the next event from the handler thread.
     ///
     /// This function will always block the current thread if
@@ -89,9 +103,6 @@ impl EventHandler {
         self.receiver
             .recv()
             .await
-            .ok_or(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "This is an IO error",
-            )))
+            .ok_or(Box::new(std::io::Error::other("This is an IO error")))
     }
 }
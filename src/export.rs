@@ -0,0 +1,108 @@
+use std::{fs::OpenOptions, io::Write, path::PathBuf};
+
+use chrono::Local;
+
+use crate::{
+    app::AppResult,
+    tcp::{AccountListItem, AccountUri},
+};
+
+const CSV_HEADER: [&str; 9] = [
+    "type", "name", "account", "password", "urls", "note", "folder", "tags", "fields",
+];
+
+/// The items as CSV, one row each after a header.
+///
+/// Several URLs, tags or fields share a cell, one per line. Fields are the
+/// typed and the custom fields as `name: value`.
+pub fn to_csv(items: &[&AccountListItem]) -> String {
+    let mut csv = csv_row(CSV_HEADER.iter().map(|h| h.to_string()));
+    for item in items {
+        let urls: Vec<String> = item.uris.iter().map(AccountUri::to_line).collect();
+        let fields: Vec<String> = item
+            .item_type
+            .fields()
+            .iter()
+            .filter(|spec| !item.type_field(spec.key).is_empty())
+            .map(|spec| format!("{}: {}", spec.label, item.type_field(spec.key)))
+            .chain(
+                item.custom_fields
+                    .iter()
+                    .map(|field| format!("{}: {}", field.name, field.value)),
+            )
+            .collect();
+        csv.push_str(&csv_row([
+            item.item_type.label().to_string(),
+            item.site_name.clone().unwrap_or_default(),
+            item.account.clone(),
+            item.password.clone(),
+            urls.join("\n"),
+            item.note.clone().unwrap_or_default(),
+            item.folder.clone().unwrap_or_default(),
+            item.tags.join("\n"),
+            fields.join("\n"),
+        ]));
+    }
+    csv
+}
+
+fn csv_row(cells: impl IntoIterator<Item = String>) -> String {
+    let cells: Vec<String> = cells.into_iter().map(|cell| csv_cell(&cell)).collect();
+    format!("{}\r\n", cells.join(","))
+}
+
+/// Quote a cell if it has to be, doubling the quotes in it.
+fn csv_cell(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// Write the items to a new CSV file in the current directory, readable by the
+/// user only since it holds the passwords in clear text.
+pub fn write_csv(items: &[&AccountListItem]) -> AppResult<PathBuf> {
+    let path = PathBuf::from(format!(
+        "ysnp-export-{}.csv",
+        Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&path)?;
+    file.write_all(to_csv(items).as_bytes())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_csv() {
+        let item = AccountListItem {
+            account: "me".to_string(),
+            password: "a,\"b\"".to_string(),
+            site_name: Some("Example".to_string()),
+            uris: vec![
+                AccountUri::parse_line("https://example.com"),
+                AccountUri::parse_line("host https://sso.example.com"),
+            ],
+            tags: vec!["work".to_string()],
+            ..AccountListItem::default()
+        };
+        let csv = to_csv(&[&item]);
+        let rows: Vec<&str> = csv.split("\r\n").collect();
+        assert_eq!(
+            rows[0],
+            "type,name,account,password,urls,note,folder,tags,fields"
+        );
+        assert_eq!(
+            rows[1],
+            "Login,Example,me,\"a,\"\"b\"\"\",\"https://example.com\nhost https://sso.example.com\",,,work,"
+        );
+        assert_eq!(rows.len(), 3);
+    }
+}
@@ -0,0 +1,563 @@
+use std::collections::BTreeMap;
+
+use url::Url;
+
+use crate::{
+    app::Focus,
+    item::{luhn_valid, ItemType},
+    strength::Strength,
+    tcp::{AccountListItem, AccountUri, CustomField, CustomFieldKind, UriMatch},
+};
+
+/// The weakest new master password accepted.
+pub const MIN_MASTER_STRENGTH: Strength = Strength::Fair;
+
+/// The inputs of the change master password screen, in order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MasterField {
+    #[default]
+    Current,
+    New,
+    Confirm,
+}
+
+impl MasterField {
+    pub const ALL: [MasterField; 3] =
+        [MasterField::Current, MasterField::New, MasterField::Confirm];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MasterField::Current => "Current password",
+            MasterField::New => "New password",
+            MasterField::Confirm => "Confirm new password",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            MasterField::Current => MasterField::New,
+            MasterField::New => MasterField::Confirm,
+            MasterField::Confirm => MasterField::Current,
+        }
+    }
+}
+
+/// Check a master password change before sending it, naming the field to fix.
+pub fn validate_master_password(
+    current: &str,
+    new: &str,
+    confirm: &str,
+) -> Result<(), (MasterField, &'static str)> {
+    if current.is_empty() {
+        return Err((MasterField::Current, "Enter the current password"));
+    }
+    if new == current {
+        return Err((MasterField::New, "Same as the current password"));
+    }
+    if Strength::of(new) < MIN_MASTER_STRENGTH {
+        return Err((MasterField::New, "Too weak"));
+    }
+    if confirm != new {
+        return Err((MasterField::Confirm, "Does not match the new password"));
+    }
+    Ok(())
+}
+
+/// What the edit form holds, independent of the text areas showing it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormValues {
+    pub account: String,
+    pub password: String,
+    pub site_name: String,
+    /// One URL per line, see [`AccountUri::parse_line`].
+    pub site_url: String,
+    pub note: String,
+    /// Comma separated.
+    pub tags: String,
+    pub folder: String,
+    pub item_type: ItemType,
+    /// One value per field of the item type's schema, in schema order.
+    pub type_fields: Vec<String>,
+    pub custom_fields: Vec<CustomField>,
+}
+
+impl FormValues {
+    /// The form as it is filled in when editing `item`.
+    pub fn from_item(item: &AccountListItem) -> Self {
+        Self {
+            account: item.account.clone(),
+            password: item.password.clone(),
+            site_name: item.site_name.clone().unwrap_or_default(),
+            site_url: if item.uris.is_empty() {
+                item.site_url.clone()
+            } else {
+                let lines: Vec<String> = item.uris.iter().map(AccountUri::to_line).collect();
+                lines.join("\n")
+            },
+            note: item.note.clone().unwrap_or_default(),
+            tags: item.tags.join(", "),
+            folder: item.folder.clone().unwrap_or_default(),
+            item_type: item.item_type,
+            type_fields: item
+                .item_type
+                .fields()
+                .iter()
+                .map(|spec| item.type_field(spec.key).to_string())
+                .collect(),
+            custom_fields: item.custom_fields.clone(),
+        }
+    }
+
+    /// The typed fields by key, empty ones left out.
+    pub fn type_field_map(&self) -> BTreeMap<String, String> {
+        self.item_type
+            .fields()
+            .iter()
+            .zip(&self.type_fields)
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(spec, value)| (spec.key.to_string(), value.clone()))
+            .collect()
+    }
+
+    /// The tags typed in the form, empty ones left out.
+    pub fn tag_list(&self) -> Vec<String> {
+        self.tags
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// The URLs typed in the form, empty lines left out.
+    pub fn uri_list(&self) -> Vec<AccountUri> {
+        self.site_url
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(AccountUri::parse_line)
+            .collect()
+    }
+
+    /// The folder path, `None` for no folder.
+    pub fn folder_path(&self) -> Option<String> {
+        if self.folder.is_empty() {
+            None
+        } else {
+            Some(self.folder.clone())
+        }
+    }
+}
+
+/// A problem with one field of the form, shown under its text area.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: Focus,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: Focus, message: impl Into<String>) -> Self {
+        Self {
+            field,
+            message: message.into(),
+        }
+    }
+}
+
+impl FormValues {
+    /// Check the form before it is sent, returning the values as they should be
+    /// stored: trimmed, with the URL normalized.
+    pub fn validate(&self) -> Result<FormValues, Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        let single_line = [
+            (Focus::Account, &self.account),
+            (Focus::Name, &self.site_name),
+            (Focus::Password, &self.password),
+            (Focus::Folder, &self.folder),
+            (Focus::Tags, &self.tags),
+        ];
+        for (field, value) in single_line {
+            if value.contains('\n') {
+                errors.push(FieldError::new(field, "Must be a single line"));
+            }
+        }
+
+        let is_login = self.item_type == ItemType::Login;
+        if is_login {
+            if self.account.trim().is_empty() {
+                errors.push(FieldError::new(Focus::Account, "Account is required"));
+            }
+            if self.password.is_empty() {
+                errors.push(FieldError::new(Focus::Password, "Password is required"));
+            }
+        } else if self.site_name.trim().is_empty() {
+            errors.push(FieldError::new(Focus::Name, "Name is required"));
+        }
+        // `tag:` search terms end at whitespace.
+        if self
+            .tag_list()
+            .iter()
+            .any(|tag| tag.contains(char::is_whitespace))
+        {
+            errors.push(FieldError::new(
+                Focus::Tags,
+                "Tags cannot contain spaces, separate them with commas",
+            ));
+        }
+
+        let site_url = if !is_login {
+            String::new()
+        } else {
+            match normalize_uris(&self.uri_list()) {
+                Ok(uris) => {
+                    let lines: Vec<String> = uris.iter().map(AccountUri::to_line).collect();
+                    lines.join("\n")
+                }
+                Err(message) => {
+                    errors.push(FieldError::new(Focus::Url, message));
+                    String::new()
+                }
+            }
+        };
+
+        let mut type_fields = Vec::new();
+        for (i, spec) in self.item_type.fields().iter().enumerate() {
+            let raw = self.type_fields.get(i).map(String::as_str).unwrap_or("");
+            let value = if spec.multiline {
+                raw.trim_end().to_string()
+            } else {
+                raw.trim().to_string()
+            };
+            if !spec.multiline && value.contains('\n') {
+                errors.push(FieldError::new(
+                    Focus::TypeField(i),
+                    "Must be a single line",
+                ));
+            } else if spec.required && value.is_empty() {
+                errors.push(FieldError::new(
+                    Focus::TypeField(i),
+                    format!("{} is required", spec.label),
+                ));
+            }
+            let value = match (self.item_type, spec.key) {
+                (ItemType::Card, "number") if !value.is_empty() => normalize_card_number(&value),
+                (ItemType::Card, "expiry") if !value.is_empty() => normalize_expiry(&value),
+                _ => Ok(value),
+            };
+            match value {
+                Ok(value) => type_fields.push(value),
+                Err(message) => {
+                    errors.push(FieldError::new(Focus::TypeField(i), message));
+                    type_fields.push(String::new());
+                }
+            }
+        }
+
+        let mut custom_fields = Vec::new();
+        for (i, field) in self.custom_fields.iter().enumerate() {
+            if field.name.contains('\n') {
+                errors.push(FieldError::new(
+                    Focus::CustomName(i),
+                    "Must be a single line",
+                ));
+            }
+            if field.value.contains('\n') {
+                errors.push(FieldError::new(
+                    Focus::CustomValue(i),
+                    "Must be a single line",
+                ));
+            }
+            if field.name.trim().is_empty() {
+                errors.push(FieldError::new(Focus::CustomName(i), "Name is required"));
+            }
+            let value = match field.kind {
+                CustomFieldKind::Text | CustomFieldKind::Hidden => Ok(field.value.clone()),
+                CustomFieldKind::Url => normalize_url(&field.value),
+                CustomFieldKind::Boolean => normalize_bool(&field.value),
+            };
+            match value {
+                Ok(value) => custom_fields.push(CustomField {
+                    name: field.name.trim().to_string(),
+                    value,
+                    kind: field.kind,
+                }),
+                Err(message) => errors.push(FieldError::new(Focus::CustomValue(i), message)),
+            }
+        }
+
+        if !errors.is_empty() {
+            let order = Focus::edit_fields(self.item_type, self.custom_fields.len());
+            errors.sort_by_key(|e| order.iter().position(|f| *f == e.field));
+            // One message per field is enough.
+            errors.dedup_by_key(|e| e.field);
+            return Err(errors);
+        }
+
+        Ok(FormValues {
+            account: if is_login {
+                self.account.trim().to_string()
+            } else {
+                String::new()
+            },
+            // Leading or trailing spaces may be part of a password.
+            password: if is_login {
+                self.password.clone()
+            } else {
+                String::new()
+            },
+            site_name: self.site_name.trim().to_string(),
+            site_url,
+            note: self.note.clone(),
+            tags: dedup_tags(self.tag_list()).join(", "),
+            folder: normalize_folder(&self.folder),
+            item_type: self.item_type,
+            type_fields,
+            custom_fields,
+        })
+    }
+}
+
+/// Drop repeated tags, ignoring case and keeping the first spelling.
+fn dedup_tags(tags: Vec<String>) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::new();
+    for tag in tags {
+        if !unique.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+            unique.push(tag);
+        }
+    }
+    unique
+}
+
+/// Trim every part of a folder path and drop empty ones: ` /Work// Infra` is `Work/Infra`.
+fn normalize_folder(folder: &str) -> String {
+    folder
+        .split('/')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Check every URL of the form, normalizing all but regular expressions.
+/// The first one is what the server knows as the site URL, so it has to be a
+/// real URL.
+fn normalize_uris(uris: &[AccountUri]) -> Result<Vec<AccountUri>, String> {
+    match uris.first() {
+        None => return Err("Site URL is required".to_string()),
+        Some(first) if !first.is_visitable() => {
+            return Err("The first URL cannot be a regex".to_string())
+        }
+        Some(_) => {}
+    }
+    uris.iter()
+        .map(|uri| {
+            let normalized = match uri.rule {
+                UriMatch::Regex => regex::Regex::new(&uri.uri)
+                    .map(|_| uri.uri.clone())
+                    .map_err(|e| format!("Invalid regex: {}", e))?,
+                _ => normalize_url(&uri.uri)?,
+            };
+            Ok(AccountUri {
+                uri: normalized,
+                rule: uri.rule,
+            })
+        })
+        .collect()
+}
+
+/// Check a card number, stored without the spaces or dashes it is often typed with.
+fn normalize_card_number(input: &str) -> Result<String, String> {
+    let number: String = input.chars().filter(|c| !matches!(c, ' ' | '-')).collect();
+    if !number.chars().all(|c| c.is_ascii_digit()) {
+        return Err("Card number can only contain digits".to_string());
+    }
+    if !(12..=19).contains(&number.len()) || !luhn_valid(&number) {
+        return Err("Not a valid card number".to_string());
+    }
+    Ok(number)
+}
+
+/// Read a card expiry typed as `MM/YY` or `MM/YYYY`, stored as `MM/YY`.
+fn normalize_expiry(input: &str) -> Result<String, String> {
+    let invalid = || "Expected MM/YY".to_string();
+    let (month, year) = input.split_once('/').ok_or_else(invalid)?;
+    let (month, year) = (month.trim(), year.trim());
+    let month: u32 = month.parse().map_err(|_| invalid())?;
+    if !(1..=12).contains(&month) || !year.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let year = match year.len() {
+        2 => year,
+        4 => &year[2..],
+        _ => return Err(invalid()),
+    };
+    Ok(format!("{:02}/{}", month, year))
+}
+
+/// Read a yes/no custom field, stored as `true` or `false`. Empty is no.
+fn normalize_bool(input: &str) -> Result<String, String> {
+    match input.trim().to_lowercase().as_str() {
+        "true" | "yes" | "y" | "1" | "on" => Ok("true".to_string()),
+        "false" | "no" | "n" | "0" | "off" | "" => Ok("false".to_string()),
+        _ => Err("Expected yes or no".to_string()),
+    }
+}
+
+/// Parse a site URL as typed by a user: `https://` is assumed when there is no
+/// scheme and international domain names are converted to punycode.
+pub fn normalize_url(input: &str) -> Result<String, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("Site URL is required".to_string());
+    }
+    let with_scheme = if input.contains("://") {
+        input.to_string()
+    } else {
+        format!("https://{}", input)
+    };
+    let url = Url::parse(&with_scheme).map_err(|e| format!("Invalid URL: {}", e))?;
+    if matches!(url.scheme(), "http" | "https") && url.host_str().is_none_or(str::is_empty) {
+        return Err("URL has no host".to_string());
+    }
+    Ok(url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> FormValues {
+        FormValues {
+            account: " me ".to_string(),
+            password: "hunter2".to_string(),
+            site_name: "Example".to_string(),
+            site_url: "example.com/login".to_string(),
+            note: "first\nsecond".to_string(),
+            tags: "work, ,Mail,mail".to_string(),
+            folder: " /Work// Infra".to_string(),
+            item_type: ItemType::Login,
+            type_fields: Vec::new(),
+            custom_fields: vec![
+                CustomField {
+                    name: " PIN ".to_string(),
+                    value: " 1234".to_string(),
+                    kind: CustomFieldKind::Hidden,
+                },
+                CustomField {
+                    name: "2FA".to_string(),
+                    value: "Yes".to_string(),
+                    kind: CustomFieldKind::Boolean,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_validate_master_password() {
+        let check = |current, new, confirm| validate_master_password(current, new, confirm);
+        assert_eq!(check("", "x", "x").unwrap_err().0, MasterField::Current);
+        assert_eq!(check("old", "old", "old").unwrap_err().0, MasterField::New);
+        assert_eq!(
+            check("old", "hunter2", "hunter2").unwrap_err().0,
+            MasterField::New
+        );
+        assert_eq!(
+            check("old", "correct horse", "correct hose").unwrap_err().0,
+            MasterField::Confirm
+        );
+        assert!(check("old", "correct horse", "correct horse").is_ok());
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = values().validate().unwrap();
+        assert_eq!(valid.account, "me");
+        assert_eq!(valid.site_url, "https://example.com/login");
+
+        let mut several = values();
+        several.site_url = "example.com\n\nhost  sso.example.org\nregex ^https://a\\.".to_string();
+        let valid = several.validate().unwrap();
+        assert_eq!(
+            valid.site_url,
+            "https://example.com/\nhost https://sso.example.org/\nregex ^https://a\\."
+        );
+        assert_eq!(valid.uri_list()[1].rule, UriMatch::Host);
+        several.site_url = "regex ^https://".to_string();
+        assert!(several.validate().is_err());
+        assert_eq!(valid.note, "first\nsecond");
+        assert_eq!(valid.tags, "work, Mail");
+        assert_eq!(valid.folder, "Work/Infra");
+        assert_eq!(valid.custom_fields[0].name, "PIN");
+        assert_eq!(valid.custom_fields[0].value, " 1234");
+        assert_eq!(valid.custom_fields[1].value, "true");
+
+        let mut invalid = values();
+        invalid.account = "".to_string();
+        invalid.site_name = "a\nb".to_string();
+        invalid.site_url = "http://".to_string();
+        invalid.tags = "two words".to_string();
+        invalid.custom_fields[0].name = "".to_string();
+        invalid.custom_fields[1].value = "maybe".to_string();
+        let errors = invalid.validate().unwrap_err();
+        let fields: Vec<Focus> = errors.iter().map(|e| e.field).collect();
+        assert_eq!(
+            fields,
+            vec![
+                Focus::Account,
+                Focus::Name,
+                Focus::Url,
+                Focus::Tags,
+                Focus::CustomName(0),
+                Focus::CustomValue(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_card() {
+        let mut card = FormValues {
+            site_name: "Visa".to_string(),
+            item_type: ItemType::Card,
+            type_fields: vec![
+                "Me".to_string(),
+                "4111 1111-1111 1111".to_string(),
+                "3/2031".to_string(),
+                String::new(),
+            ],
+            ..FormValues::default()
+        };
+        let valid = card.validate().unwrap();
+        assert_eq!(valid.type_fields[1], "4111111111111111");
+        assert_eq!(valid.type_fields[2], "03/31");
+        assert_eq!(valid.type_field_map().len(), 3);
+
+        card.site_name = String::new();
+        card.type_fields[1] = "4111 1111 1111 1112".to_string();
+        card.type_fields[2] = "13/31".to_string();
+        let fields: Vec<Focus> = card
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(
+            fields,
+            vec![Focus::Name, Focus::TypeField(1), Focus::TypeField(2)]
+        );
+    }
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(
+            normalize_url("Bücher.example").unwrap(),
+            "https://xn--bcher-kva.example/"
+        );
+        assert_eq!(
+            normalize_url("http://example.com").unwrap(),
+            "http://example.com/"
+        );
+        assert!(normalize_url("  ").is_err());
+        assert!(normalize_url("https://exa mple.com").is_err());
+    }
+}
@@ -1,109 +1,345 @@
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::app::{App, AppResult, Status};
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crate::app::{App, AppResult, Focus, Status};
+use crate::keymap::{Command, Context, KeyPress, Resolution};
+use crate::ui::running::RunningPage;
+use crossterm::event::{KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::Rect;
 use tokio::sync::RwLock;
 
 /// Handles the key events and updates the state of [`App`].
 pub async fn handle_key_events(key_event: KeyEvent, app: Arc<RwLock<App>>) -> AppResult<()> {
     let mut app = app.write().await;
-    match app.status {
-        Status::Login => {
-            match key_event.code {
-                // Exit application on `Ctrl-C`
-                KeyCode::Char('c') | KeyCode::Char('C') => {
-                    if key_event.modifiers == KeyModifiers::CONTROL {
-                        app.quit();
-                    } else {
-                        app.login_input(key_event);
-                    }
-                }
-                KeyCode::Esc => {
-                    app.quit();
-                }
-                KeyCode::Enter => {
-                    app.login().await;
-                }
-                _ => app.login_input(key_event),
+    app.request_render();
+
+    // Any key closes the help overlay.
+    if app.show_help {
+        app.pending_keys.clear();
+        app.toggle_help();
+        return Ok(());
+    }
+
+    let context = app.key_context();
+    let key = KeyPress::from(key_event);
+    let mut keys = std::mem::take(&mut app.pending_keys);
+    keys.push(key);
+    let mut resolution = app.keymap.resolve(context, &keys);
+    // A key that breaks a sequence starts over on its own; the characters
+    // before it were meant as text after all.
+    if resolution == Resolution::Unbound && keys.len() > 1 {
+        let mut typed = std::mem::replace(&mut keys, vec![key]);
+        typed.pop();
+        for key in typed {
+            if matches!(key.code, KeyCode::Char(_)) && key.modifiers.is_empty() {
+                text_input(&mut app, context, KeyEvent::new(key.code, key.modifiers));
             }
         }
-        Status::Delete => match key_event.code {
-            KeyCode::Char('y') | KeyCode::Char('Y') => {
-                app.delete().await;
-            }
-            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
-                app.cancel_delete();
-            }
-            _ => {}
-        },
-        Status::Search => {
-            match key_event.code {
-                // Exit application on `ESC` or `q`
-                KeyCode::Esc | KeyCode::Tab => {
-                    app.search();
+        resolution = app.keymap.resolve(context, &keys);
+    }
+
+    match resolution {
+        Resolution::Command(command) => run_command(command, &mut app).await,
+        Resolution::Pending => app.pending_keys = keys,
+        Resolution::Unbound => text_input(&mut app, context, key_event),
+    }
+    Ok(())
+}
+
+/// Sends an unbound key to whichever text area is active.
+fn text_input(app: &mut App, context: Context, key_event: KeyEvent) {
+    match context {
+        Context::Login => app.login_input(key_event),
+        Context::Search => app.search_input(key_event),
+        Context::Edit => app.edit_input(key_event),
+        Context::BulkTag => app.bulk_tag_input(key_event),
+        Context::MasterPassword => app.master_password_input(key_event),
+        Context::List
+        | Context::Delete
+        | Context::Discard
+        | Context::PickUrl
+        | Context::History
+        | Context::Trash
+        | Context::Purge => {}
+    }
+}
+
+/// Runs a [`Command`] against the [`App`].
+async fn run_command(command: Command, app: &mut App) {
+    match command {
+        Command::Quit => app.quit(),
+        Command::Login => app.login().await,
+        Command::ToggleHelp => app.toggle_help(),
+        Command::Search | Command::ExitSearch => app.search(),
+        Command::Edit => app.edit().await,
+        Command::NewItem => app.new_item(),
+        Command::SelectNext => app.list_select_next_item(),
+        Command::SelectPrevious => app.list_select_before_item(),
+        Command::SelectFirst => app.list_select_first_item(),
+        Command::SelectLast => app.list_select_last_item(),
+        Command::NextFolder => app.next_folder(),
+        Command::PreviousFolder => app.previous_folder(),
+        Command::Visit => app.visit(),
+        Command::TryDelete => app.try_delete().await,
+        Command::ConfirmDelete => app.delete().await,
+        Command::CancelDelete => app.cancel_delete(),
+        Command::FocusNext => app.focus_next(),
+        Command::SaveEdit => app.save_edit().await,
+        Command::CancelEdit => app.cancel_edit(),
+        Command::AddCustomField => app.add_custom_field(),
+        Command::RemoveCustomField => app.remove_custom_field(),
+        Command::MoveCustomFieldUp => app.move_custom_field(-1),
+        Command::MoveCustomFieldDown => app.move_custom_field(1),
+        Command::CycleCustomFieldKind => app.cycle_custom_field_kind(),
+        Command::CycleItemType => app.cycle_item_type(),
+        Command::ConfirmDiscard => app.discard_edit(),
+        Command::CancelDiscard => app.keep_editing(),
+        Command::NextUrl => app.pick_url_next(),
+        Command::PreviousUrl => app.pick_url_previous(),
+        Command::OpenUrl => app.open_picked_url(),
+        Command::CancelPickUrl => app.cancel_pick_url(),
+        Command::ShowHistory => app.show_history().await,
+        Command::CopyPassword => app.copy_password().await,
+        Command::ToggleRecent => app.toggle_recent(),
+        Command::NextHistoryEntry => app.history_next(),
+        Command::PreviousHistoryEntry => app.history_previous(),
+        Command::RevealHistory => app.toggle_history_passwords(),
+        Command::RestorePassword => app.restore_password().await,
+        Command::CloseHistory => app.close_history(),
+        Command::ShowTrash => app.show_trash().await,
+        Command::Undo => app.undo().await,
+        Command::NextTrashItem => app.trash_next(),
+        Command::PreviousTrashItem => app.trash_previous(),
+        Command::RestoreItem => app.restore_from_trash().await,
+        Command::TryPurge => app.try_purge(),
+        Command::ConfirmPurge => app.purge().await,
+        Command::CancelPurge => app.cancel_purge(),
+        Command::CloseTrash => app.close_trash(),
+        Command::ToggleMark => app.toggle_mark(),
+        Command::ToggleVisual => app.toggle_visual(),
+        Command::MarkAll => app.mark_all_visible(),
+        Command::ClearMarks => app.clear_marks(),
+        Command::BulkTag => app.try_bulk_tag(),
+        Command::ApplyBulkTag => app.bulk_tag().await,
+        Command::CancelBulkTag => app.cancel_bulk_tag(),
+        Command::ExportMarked => app.export_marked().await,
+        Command::ChangeMasterPassword => app.show_master_password(),
+        Command::NextMasterField => app.next_master_field(),
+        Command::SaveMasterPassword => app.save_master_password().await,
+        Command::CancelMasterPassword => app.cancel_master_password(),
+        Command::CheckDeadLinks => app.check_dead_links().await,
+        Command::NextProfile => app.cycle_profile(1).await,
+        Command::PreviousProfile => app.cycle_profile(-1).await,
+    }
+}
+
+/// Handles bracketed paste and updates the state of [`App`].
+pub async fn handle_paste_events(text: String, app: Arc<RwLock<App>>) -> AppResult<()> {
+    let mut app = app.write().await;
+    app.request_render();
+    if app.show_help {
+        return Ok(());
+    }
+    app.pending_keys.clear();
+    app.paste(&text);
+    Ok(())
+}
+
+/// How close two clicks on the same item have to be to count as a double click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Handles the mouse events and updates the state of [`App`].
+pub async fn handle_mouse_events(mouse: MouseEvent, app: Arc<RwLock<App>>) -> AppResult<()> {
+    let mut app = app.write().await;
+    let click = mouse.kind == MouseEventKind::Down(MouseButton::Left);
+    let scroll = match mouse.kind {
+        MouseEventKind::ScrollDown => 1,
+        MouseEventKind::ScrollUp => -1,
+        _ => 0,
+    };
+
+    // Plain mouse moves change nothing on screen.
+    if click || scroll != 0 {
+        app.request_render();
+    }
+
+    if app.show_help {
+        if click {
+            app.toggle_help();
+        }
+        return Ok(());
+    }
+
+    let (width, height) = crossterm::terminal::size()?;
+    let (main_area, _) = RunningPage::root_areas(Rect::new(0, 0, width, height));
+    let at = |area: Rect| {
+        area.x <= mouse.column
+            && mouse.column < area.x + area.width
+            && area.y <= mouse.row
+            && mouse.row < area.y + area.height
+    };
+
+    match app.status {
+        Status::List | Status::Search => {
+            let areas = RunningPage::list_areas(main_area);
+            let custom_fields = app.selected_item().map_or(0, |i| i.custom_fields.len());
+            let note_area = RunningPage::detail_areas(areas.detail, custom_fields).note;
+            if at(areas.list) {
+                if scroll > 0 {
+                    app.list_select_next_item();
+                } else if scroll < 0 {
+                    app.list_select_before_item();
+                } else if click {
+                    click_list(&mut app, areas.list, mouse.row);
                 }
-                _ => {
-                    app.search_input(key_event);
+            } else if at(areas.folders) && click {
+                click_folders(&mut app, areas.folders, mouse.row);
+            } else if at(areas.search) && click {
+                if let Status::List = app.status {
+                    app.search();
                 }
+            } else if at(note_area) && scroll != 0 {
+                app.scroll_note(scroll);
             }
         }
-        Status::List | Status::Edit => {
-            match &app.focus {
-                crate::app::Focus::List => {
-                    // Handle key events when the focus is on the list.
-                    match key_event.code {
-                        // Exit application on `ESC` or `q`
-                        KeyCode::Char('/') | KeyCode::Tab => {
-                            app.search();
-                        }
-                        KeyCode::Esc | KeyCode::Char('q') => {
-                            app.quit();
-                        }
-                        // Exit application on `Ctrl-C`
-                        KeyCode::Char('c') | KeyCode::Char('C') => {
-                            if key_event.modifiers == KeyModifiers::CONTROL {
-                                app.quit();
-                            }
-                        }
-                        KeyCode::Char('e') => {
-                            app.edit();
-                        }
-                        KeyCode::Char('n') => {
-                            app.new_item();
-                        }
-                        KeyCode::Char('j') => {
-                            app.list_select_next_item();
-                        }
-                        KeyCode::Char('k') => {
-                            app.list_select_before_item();
-                        }
-                        KeyCode::Char('d') => {
-                            app.try_delete();
-                        }
-                        KeyCode::Enter => {
-                            app.visit();
-                        }
-                        // Other handlers you could add here.
-                        _ => {}
-                    }
-                }
-                _ => {
-                    // Handle key events when edit
-                    match key_event.code {
-                        // Exit application on `ESC` or `q`
-                        KeyCode::Esc => {
-                            app.quit_edit().await;
-                        }
-                        KeyCode::Tab => {
-                            app.focus_next();
-                        }
-                        _ => {
-                            app.edit_input(key_event);
-                        }
+        Status::Edit => {
+            let areas = RunningPage::edit_areas(main_area, app.page.item_type);
+            let mut fields: Vec<(Rect, Focus)> = areas
+                .fields
+                .into_iter()
+                .map(|(focus, area)| (area, focus))
+                .collect();
+            let custom_areas =
+                RunningPage::custom_field_areas(areas.custom_fields, app.page.custom_fields.len());
+            for (i, (name, value)) in custom_areas.into_iter().enumerate() {
+                fields.push((name, Focus::CustomName(i)));
+                fields.push((value, Focus::CustomValue(i)));
+            }
+            if let Some((_, focus)) = fields.into_iter().find(|(area, _)| at(*area)) {
+                if click {
+                    app.set_focus(focus);
+                } else if scroll != 0 {
+                    if let Some(textarea) = app.edit_textarea(focus) {
+                        textarea.input(mouse);
                     }
                 }
             }
         }
-    };
+        Status::Login
+        | Status::Delete
+        | Status::Discard
+        | Status::PickUrl
+        | Status::History
+        | Status::Trash
+        | Status::Purge
+        | Status::BulkTag
+        | Status::MasterPassword => {}
+    }
     Ok(())
 }
+
+/// Select the clicked row of the account list, visiting it on a double click.
+fn click_list(app: &mut App, list_area: Rect, row: u16) {
+    // Leave the search box so list keys work on the clicked item.
+    if let Status::Search = app.status {
+        app.search();
+    }
+
+    let visible = app.visible_items();
+    let selected = visible
+        .iter()
+        .position(|i| *i == app.account_list.selected)
+        .unwrap_or(0);
+    let Some(index) = clicked_row(list_area, row, selected, visible.len()) else {
+        return;
+    };
+    let index = visible[index];
+
+    let now = Instant::now();
+    let double =
+        matches!(app.last_click, Some((at, last)) if last == index && now - at < DOUBLE_CLICK);
+    app.list_select(index);
+    if double {
+        app.last_click = None;
+        app.visit();
+    } else {
+        app.last_click = Some((now, index));
+    }
+}
+
+/// Narrow the list to the clicked folder of the tree.
+fn click_folders(app: &mut App, folders_area: Rect, row: u16) {
+    let rows = RunningPage::folder_rows(&app.account_list);
+    let current = rows
+        .iter()
+        .position(|f| f.as_deref() == app.folder.as_deref())
+        .unwrap_or(0);
+    if let Some(index) = clicked_row(folders_area, row, current, rows.len()) {
+        app.set_folder(rows[index].clone());
+    }
+}
+
+/// Which of the `len` rows of a bordered list with row `selected` highlighted
+/// is on screen `row`, if any.
+fn clicked_row(area: Rect, row: u16, selected: usize, len: usize) -> Option<usize> {
+    // Skip the borders.
+    let row = row.checked_sub(area.y + 1)?;
+    if row + 2 >= area.height {
+        return None;
+    }
+    let index = RunningPage::list_offset(area, selected) + row as usize;
+    (index < len).then_some(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::{Keymap, KeymapConfig};
+    use crate::tcp::AccountListItem;
+    use crossterm::event::KeyModifiers;
+
+    #[test]
+    fn test_click_list_while_searching() {
+        let mut app = App::default();
+        app.status = Status::List;
+        app.account_list.list = ["alpha", "beta", "gamma"]
+            .iter()
+            .map(|account| AccountListItem {
+                account: account.to_string(),
+                ..AccountListItem::default()
+            })
+            .collect();
+        app.page.search_textarea.insert_str("gamma");
+        let list_area = Rect::new(0, 0, 20, 10);
+
+        // The first row under the border is the only item shown.
+        click_list(&mut app, list_area, 1);
+        assert_eq!(app.account_list.selected, 2);
+        click_list(&mut app, list_area, 2);
+        assert_eq!(app.account_list.selected, 2);
+    }
+
+    #[tokio::test]
+    async fn test_broken_sequence_is_typed() {
+        let config: KeymapConfig = toml::from_str(
+            r#"
+            [bindings.edit]
+            cancel_edit = ["j k"]
+            "#,
+        )
+        .unwrap();
+        let mut app = App::default();
+        app.keymap = Keymap::from_config(&config).unwrap();
+        app.status = Status::Edit;
+        app.focus = Focus::Account;
+        let app = Arc::new(RwLock::new(app));
+
+        for c in ['j', 'a'] {
+            let key = KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+            handle_key_events(key, app.clone()).await.unwrap();
+        }
+        let app = app.read().await;
+        assert_eq!(app.page.account_textarea.lines(), ["ja"]);
+        assert!(matches!(app.status, Status::Edit));
+    }
+}
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+
+/// What kind of secret an item holds.
+///
+/// A [`ItemType::Login`] is the classic website account with account, password
+/// and URLs. Every other type is named by `site_name` and keeps its data in
+/// the fields of its [`ItemType::fields`] schema.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemType {
+    #[default]
+    Login,
+    SecureNote,
+    Card,
+    Identity,
+    SshKey,
+    ApiToken,
+}
+
+/// One field of an item type's schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldSpec {
+    /// How the field is stored on the wire.
+    pub key: &'static str,
+    pub label: &'static str,
+    /// Masked in the detail view and the edit form.
+    pub secret: bool,
+    pub multiline: bool,
+    pub required: bool,
+}
+
+const fn field(key: &'static str, label: &'static str) -> FieldSpec {
+    FieldSpec {
+        key,
+        label,
+        secret: false,
+        multiline: false,
+        required: false,
+    }
+}
+
+impl FieldSpec {
+    const fn secret(mut self) -> Self {
+        self.secret = true;
+        self
+    }
+
+    const fn multiline(mut self) -> Self {
+        self.multiline = true;
+        self
+    }
+
+    const fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+}
+
+const CARD_FIELDS: &[FieldSpec] = &[
+    field("cardholder", "Cardholder"),
+    field("number", "Number").secret().required(),
+    field("expiry", "Expiry (MM/YY)"),
+    field("cvv", "CVV").secret(),
+];
+
+const IDENTITY_FIELDS: &[FieldSpec] = &[
+    field("full_name", "Full name").required(),
+    field("email", "Email"),
+    field("phone", "Phone"),
+    field("address", "Address").multiline(),
+];
+
+const SSH_KEY_FIELDS: &[FieldSpec] = &[
+    field("public_key", "Public key").multiline(),
+    field("private_key", "Private key")
+        .secret()
+        .multiline()
+        .required(),
+    field("passphrase", "Passphrase").secret(),
+];
+
+const API_TOKEN_FIELDS: &[FieldSpec] = &[
+    field("token", "Token").secret().required(),
+    field("endpoint", "Endpoint"),
+    field("expires", "Expires"),
+];
+
+impl ItemType {
+    pub const ALL: [ItemType; 6] = [
+        ItemType::Login,
+        ItemType::SecureNote,
+        ItemType::Card,
+        ItemType::Identity,
+        ItemType::SshKey,
+        ItemType::ApiToken,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ItemType::Login => "Login",
+            ItemType::SecureNote => "Secure note",
+            ItemType::Card => "Card",
+            ItemType::Identity => "Identity",
+            ItemType::SshKey => "SSH key",
+            ItemType::ApiToken => "API token",
+        }
+    }
+
+    /// Shown in front of the item in the account list.
+    pub fn icon(&self) -> &'static str {
+        match self {
+            ItemType::Login => "@",
+            ItemType::SecureNote => "≡",
+            ItemType::Card => "$",
+            ItemType::Identity => "☺",
+            ItemType::SshKey => "»",
+            ItemType::ApiToken => "§",
+        }
+    }
+
+    /// The fields the type has on top of name, folder, tags, note and custom fields.
+    pub fn fields(&self) -> &'static [FieldSpec] {
+        match self {
+            ItemType::Login | ItemType::SecureNote => &[],
+            ItemType::Card => CARD_FIELDS,
+            ItemType::Identity => IDENTITY_FIELDS,
+            ItemType::SshKey => SSH_KEY_FIELDS,
+            ItemType::ApiToken => API_TOKEN_FIELDS,
+        }
+    }
+
+    /// The field "copy password" copies, `None` for the password of a login.
+    pub fn secret_field(&self) -> Option<&'static str> {
+        match self {
+            ItemType::Login | ItemType::SecureNote | ItemType::Identity => None,
+            ItemType::Card => Some("number"),
+            ItemType::SshKey => Some("private_key"),
+            ItemType::ApiToken => Some("token"),
+        }
+    }
+
+    /// The type after this one, to switch between them in the edit form.
+    pub fn next(&self) -> Self {
+        let i = Self::ALL.iter().position(|t| t == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    pub fn code(&self) -> i32 {
+        match self {
+            ItemType::Login => 0,
+            ItemType::SecureNote => 1,
+            ItemType::Card => 2,
+            ItemType::Identity => 3,
+            ItemType::SshKey => 4,
+            ItemType::ApiToken => 5,
+        }
+    }
+
+    pub fn from_code(code: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.code() == code)
+    }
+}
+
+/// Does `number` pass the Luhn checksum every payment card number has?
+pub fn luhn_valid(number: &str) -> bool {
+    let digits: Option<Vec<u32>> = number.chars().rev().map(|c| c.to_digit(10)).collect();
+    let Some(digits) = digits else {
+        return false;
+    };
+    let sum: u32 = digits
+        .iter()
+        .enumerate()
+        .map(|(i, d)| match (i % 2, d * 2) {
+            (1, doubled) if doubled > 9 => doubled - 9,
+            (1, doubled) => doubled,
+            _ => *d,
+        })
+        .sum();
+    !digits.is_empty() && sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_type() {
+        for item_type in ItemType::ALL {
+            assert_eq!(ItemType::from_code(item_type.code()), Some(item_type));
+            if let Some(key) = item_type.secret_field() {
+                assert!(item_type.fields().iter().any(|f| f.key == key));
+            }
+        }
+        assert_eq!(ItemType::ApiToken.next(), ItemType::Login);
+        assert!(luhn_valid("4111111111111111"));
+        assert!(!luhn_valid("4111111111111112"));
+        assert!(!luhn_valid(""));
+    }
+}
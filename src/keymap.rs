@@ -0,0 +1,835 @@
+use std::collections::BTreeMap;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+use crate::app::AppResult;
+
+/// Where a key binding is active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Context {
+    Login,
+    List,
+    Edit,
+    Search,
+    Delete,
+    Discard,
+    /// Choosing which URL of an account to open.
+    #[serde(rename = "pick_url")]
+    PickUrl,
+    /// The password history of an account.
+    History,
+    /// The deleted accounts.
+    Trash,
+    /// Deleting an account in the trash for good.
+    Purge,
+    /// Typing the tags to change on the marked accounts.
+    #[serde(rename = "bulk_tag")]
+    BulkTag,
+    /// Changing the master password.
+    #[serde(rename = "master_password")]
+    MasterPassword,
+}
+
+impl Context {
+    pub const ALL: [Context; 12] = [
+        Context::Login,
+        Context::List,
+        Context::Edit,
+        Context::Search,
+        Context::Delete,
+        Context::Discard,
+        Context::PickUrl,
+        Context::History,
+        Context::Trash,
+        Context::Purge,
+        Context::BulkTag,
+        Context::MasterPassword,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Context::Login => "Login",
+            Context::List => "List",
+            Context::Edit => "Edit",
+            Context::Search => "Search",
+            Context::Delete => "Delete",
+            Context::Discard => "Discard changes",
+            Context::PickUrl => "Open URL",
+            Context::History => "Password history",
+            Context::Trash => "Trash",
+            Context::Purge => "Delete for good",
+            Context::BulkTag => "Tag marked",
+            Context::MasterPassword => "Change master password",
+        }
+    }
+}
+
+/// Everything a key can be bound to.
+///
+/// The snake_case variant name is what the config file uses, e.g. `select_next`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    Quit,
+    Login,
+    ToggleHelp,
+    Search,
+    ExitSearch,
+    Edit,
+    NewItem,
+    SelectNext,
+    SelectPrevious,
+    SelectFirst,
+    SelectLast,
+    NextFolder,
+    PreviousFolder,
+    Visit,
+    TryDelete,
+    ConfirmDelete,
+    CancelDelete,
+    FocusNext,
+    SaveEdit,
+    CancelEdit,
+    AddCustomField,
+    RemoveCustomField,
+    MoveCustomFieldUp,
+    MoveCustomFieldDown,
+    CycleCustomFieldKind,
+    CycleItemType,
+    ConfirmDiscard,
+    CancelDiscard,
+    NextUrl,
+    PreviousUrl,
+    OpenUrl,
+    CancelPickUrl,
+    ShowHistory,
+    CopyPassword,
+    ToggleRecent,
+    NextHistoryEntry,
+    PreviousHistoryEntry,
+    RevealHistory,
+    RestorePassword,
+    CloseHistory,
+    ShowTrash,
+    Undo,
+    NextTrashItem,
+    PreviousTrashItem,
+    RestoreItem,
+    TryPurge,
+    ConfirmPurge,
+    CancelPurge,
+    CloseTrash,
+    ToggleMark,
+    ToggleVisual,
+    MarkAll,
+    ClearMarks,
+    BulkTag,
+    ApplyBulkTag,
+    CancelBulkTag,
+    ExportMarked,
+    CheckDeadLinks,
+    ChangeMasterPassword,
+    NextMasterField,
+    SaveMasterPassword,
+    CancelMasterPassword,
+    NextProfile,
+    PreviousProfile,
+}
+
+impl Command {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Command::Quit => "quit",
+            Command::Login => "unlock",
+            Command::ToggleHelp => "help",
+            Command::Search => "search",
+            Command::ExitSearch => "back to list",
+            Command::Edit => "edit",
+            Command::NewItem => "new",
+            Command::SelectNext => "next",
+            Command::SelectPrevious => "previous",
+            Command::SelectFirst => "first",
+            Command::SelectLast => "last",
+            Command::NextFolder => "next folder",
+            Command::PreviousFolder => "previous folder",
+            Command::Visit => "open url",
+            Command::TryDelete => "delete",
+            Command::ConfirmDelete => "confirm",
+            Command::CancelDelete => "cancel",
+            Command::FocusNext => "next field",
+            Command::SaveEdit => "save",
+            Command::CancelEdit => "cancel",
+            Command::AddCustomField => "add field",
+            Command::RemoveCustomField => "remove field",
+            Command::MoveCustomFieldUp => "move field up",
+            Command::MoveCustomFieldDown => "move field down",
+            Command::CycleCustomFieldKind => "field type",
+            Command::CycleItemType => "item type",
+            Command::ConfirmDiscard => "discard changes",
+            Command::CancelDiscard => "keep editing",
+            Command::NextUrl => "next",
+            Command::PreviousUrl => "previous",
+            Command::OpenUrl => "open",
+            Command::CancelPickUrl => "cancel",
+            Command::ShowHistory => "password history",
+            Command::CopyPassword => "copy password",
+            Command::ToggleRecent => "recently used",
+            Command::NextHistoryEntry => "next",
+            Command::PreviousHistoryEntry => "previous",
+            Command::RevealHistory => "show/hide passwords",
+            Command::RestorePassword => "restore",
+            Command::CloseHistory => "close",
+            Command::ShowTrash => "trash",
+            Command::Undo => "undo delete",
+            Command::NextTrashItem => "next",
+            Command::PreviousTrashItem => "previous",
+            Command::RestoreItem => "restore",
+            Command::TryPurge => "delete for good",
+            Command::ConfirmPurge => "confirm",
+            Command::CancelPurge => "cancel",
+            Command::CloseTrash => "close",
+            Command::ToggleMark => "mark",
+            Command::ToggleVisual => "mark range",
+            Command::MarkAll => "mark all shown",
+            Command::ClearMarks => "clear marks",
+            Command::BulkTag => "tag marked",
+            Command::ApplyBulkTag => "apply",
+            Command::CancelBulkTag => "cancel",
+            Command::ExportMarked => "export marked",
+            Command::CheckDeadLinks => "check links",
+            Command::ChangeMasterPassword => "change master password",
+            Command::NextMasterField => "next field",
+            Command::SaveMasterPassword => "change",
+            Command::CancelMasterPassword => "cancel",
+            Command::NextProfile => "next vault",
+            Command::PreviousProfile => "previous vault",
+        }
+    }
+}
+
+/// A single key press with its modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyPress {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl From<KeyEvent> for KeyPress {
+    /// Crossterm reports shifted characters as the uppercase char *and* the shift
+    /// modifier; bindings are written against the char alone.
+    fn from(key: KeyEvent) -> Self {
+        let modifiers = match key.code {
+            KeyCode::Char(_) => key.modifiers - KeyModifiers::SHIFT,
+            _ => key.modifiers,
+        };
+        Self {
+            code: key.code,
+            modifiers,
+        }
+    }
+}
+
+impl KeyPress {
+    /// Parse a key written as in the config file: `j`, `G`, `enter`, `f1`,
+    /// `ctrl-c`, `alt-<`, `shift-tab`.
+    pub fn parse(s: &str) -> AppResult<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        loop {
+            let lower = rest.to_lowercase();
+            let Some(prefix) = ["ctrl-", "alt-", "shift-"]
+                .into_iter()
+                .find(|p| lower.starts_with(p) && rest.len() > p.len())
+            else {
+                break;
+            };
+            modifiers |= match prefix {
+                "ctrl-" => KeyModifiers::CONTROL,
+                "alt-" => KeyModifiers::ALT,
+                _ => KeyModifiers::SHIFT,
+            };
+            rest = &rest[prefix.len()..];
+        }
+
+        let code = match rest.to_lowercase().as_str() {
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers -= KeyModifiers::SHIFT;
+                KeyCode::BackTab
+            }
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "space" => KeyCode::Char(' '),
+            "backspace" => KeyCode::Backspace,
+            "del" | "delete" => KeyCode::Delete,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            name => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => {
+                        if modifiers.contains(KeyModifiers::SHIFT) {
+                            modifiers -= KeyModifiers::SHIFT;
+                            KeyCode::Char(c.to_ascii_uppercase())
+                        } else {
+                            KeyCode::Char(c)
+                        }
+                    }
+                    _ => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                        Some(n) => KeyCode::F(n),
+                        None => return Err(format!("Unknown key `{}`", s).into()),
+                    },
+                }
+            }
+        };
+        Ok(Self { code, modifiers })
+    }
+
+    /// Human readable name of the key, e.g. `Ctrl-c`, `Enter`, `F1`.
+    pub fn label(&self) -> String {
+        let key = match self.code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            KeyCode::Enter => "Enter".to_string(),
+            KeyCode::Esc => "Esc".to_string(),
+            KeyCode::Tab => "Tab".to_string(),
+            KeyCode::BackTab => "S-Tab".to_string(),
+            KeyCode::Backspace => "Backspace".to_string(),
+            KeyCode::Delete => "Del".to_string(),
+            KeyCode::Up => "Up".to_string(),
+            KeyCode::Down => "Down".to_string(),
+            KeyCode::Left => "Left".to_string(),
+            KeyCode::Right => "Right".to_string(),
+            KeyCode::Home => "Home".to_string(),
+            KeyCode::End => "End".to_string(),
+            KeyCode::PageUp => "PgUp".to_string(),
+            KeyCode::PageDown => "PgDn".to_string(),
+            other => format!("{:?}", other),
+        };
+        let mut label = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            label += "Ctrl-";
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            label += "Alt-";
+        }
+        label + &key
+    }
+}
+
+/// Parse a whitespace separated key sequence such as `g g` or `ctrl-x ctrl-c`.
+fn parse_sequence(s: &str) -> AppResult<Vec<KeyPress>> {
+    let keys = s
+        .split_whitespace()
+        .map(KeyPress::parse)
+        .collect::<AppResult<Vec<_>>>()?;
+    if keys.is_empty() {
+        return Err("Empty key binding".into());
+    }
+    Ok(keys)
+}
+
+fn sequence_label(keys: &[KeyPress]) -> String {
+    keys.iter()
+        .map(KeyPress::label)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub context: Context,
+    pub keys: Vec<KeyPress>,
+    pub command: Command,
+}
+
+/// Built-in sets of bindings to start from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Default,
+    Vim,
+    Emacs,
+}
+
+/// The bindings shipped with the client, in the order they are listed in the help.
+const DEFAULT_BINDINGS: &[(Context, &str, Command)] = &[
+    (Context::Login, "enter", Command::Login),
+    (Context::Login, "esc", Command::Quit),
+    (Context::Login, "ctrl-c", Command::Quit),
+    (Context::Login, "ctrl-C", Command::Quit),
+    (Context::Login, "f1", Command::ToggleHelp),
+    (Context::Login, "tab", Command::NextProfile),
+    (Context::Login, "backtab", Command::PreviousProfile),
+    (Context::List, "j", Command::SelectNext),
+    (Context::List, "down", Command::SelectNext),
+    (Context::List, "k", Command::SelectPrevious),
+    (Context::List, "up", Command::SelectPrevious),
+    (Context::List, "home", Command::SelectFirst),
+    (Context::List, "end", Command::SelectLast),
+    (Context::List, "]", Command::NextFolder),
+    (Context::List, "[", Command::PreviousFolder),
+    (Context::List, "h", Command::ShowHistory),
+    (Context::List, "y", Command::CopyPassword),
+    (Context::List, "r", Command::ToggleRecent),
+    (Context::List, "t", Command::ShowTrash),
+    (Context::List, "u", Command::Undo),
+    (Context::List, "space", Command::ToggleMark),
+    (Context::List, "v", Command::ToggleVisual),
+    (Context::List, "*", Command::MarkAll),
+    (Context::List, "-", Command::ClearMarks),
+    (Context::List, "T", Command::BulkTag),
+    (Context::List, "X", Command::ExportMarked),
+    (Context::List, "L", Command::CheckDeadLinks),
+    (Context::List, "P", Command::ChangeMasterPassword),
+    (Context::List, "p", Command::NextProfile),
+    (Context::List, "enter", Command::Visit),
+    (Context::List, "/", Command::Search),
+    (Context::List, "tab", Command::Search),
+    (Context::List, "e", Command::Edit),
+    (Context::List, "n", Command::NewItem),
+    (Context::List, "d", Command::TryDelete),
+    (Context::List, "?", Command::ToggleHelp),
+    (Context::List, "f1", Command::ToggleHelp),
+    (Context::List, "esc", Command::Quit),
+    (Context::List, "q", Command::Quit),
+    (Context::List, "ctrl-c", Command::Quit),
+    (Context::List, "ctrl-C", Command::Quit),
+    (Context::Edit, "tab", Command::FocusNext),
+    (Context::Edit, "ctrl-s", Command::SaveEdit),
+    (Context::Edit, "esc", Command::CancelEdit),
+    (Context::Edit, "alt-a", Command::AddCustomField),
+    (Context::Edit, "alt-x", Command::RemoveCustomField),
+    (Context::Edit, "alt-up", Command::MoveCustomFieldUp),
+    (Context::Edit, "alt-down", Command::MoveCustomFieldDown),
+    (Context::Edit, "alt-t", Command::CycleCustomFieldKind),
+    (Context::Edit, "alt-i", Command::CycleItemType),
+    (Context::Edit, "f1", Command::ToggleHelp),
+    (Context::Search, "esc", Command::ExitSearch),
+    (Context::Search, "tab", Command::ExitSearch),
+    (Context::Search, "f1", Command::ToggleHelp),
+    (Context::Delete, "y", Command::ConfirmDelete),
+    (Context::Delete, "Y", Command::ConfirmDelete),
+    (Context::Delete, "n", Command::CancelDelete),
+    (Context::Delete, "N", Command::CancelDelete),
+    (Context::Delete, "esc", Command::CancelDelete),
+    (Context::Delete, "f1", Command::ToggleHelp),
+    (Context::Discard, "y", Command::ConfirmDiscard),
+    (Context::Discard, "Y", Command::ConfirmDiscard),
+    (Context::Discard, "n", Command::CancelDiscard),
+    (Context::Discard, "N", Command::CancelDiscard),
+    (Context::Discard, "esc", Command::CancelDiscard),
+    (Context::Discard, "f1", Command::ToggleHelp),
+    (Context::PickUrl, "j", Command::NextUrl),
+    (Context::PickUrl, "down", Command::NextUrl),
+    (Context::PickUrl, "k", Command::PreviousUrl),
+    (Context::PickUrl, "up", Command::PreviousUrl),
+    (Context::PickUrl, "enter", Command::OpenUrl),
+    (Context::PickUrl, "esc", Command::CancelPickUrl),
+    (Context::PickUrl, "q", Command::CancelPickUrl),
+    (Context::PickUrl, "f1", Command::ToggleHelp),
+    (Context::History, "j", Command::NextHistoryEntry),
+    (Context::History, "down", Command::NextHistoryEntry),
+    (Context::History, "k", Command::PreviousHistoryEntry),
+    (Context::History, "up", Command::PreviousHistoryEntry),
+    (Context::History, "v", Command::RevealHistory),
+    (Context::History, "r", Command::RestorePassword),
+    (Context::History, "esc", Command::CloseHistory),
+    (Context::History, "q", Command::CloseHistory),
+    (Context::History, "f1", Command::ToggleHelp),
+    (Context::Trash, "j", Command::NextTrashItem),
+    (Context::Trash, "down", Command::NextTrashItem),
+    (Context::Trash, "k", Command::PreviousTrashItem),
+    (Context::Trash, "up", Command::PreviousTrashItem),
+    (Context::Trash, "r", Command::RestoreItem),
+    (Context::Trash, "x", Command::TryPurge),
+    (Context::Trash, "delete", Command::TryPurge),
+    (Context::Trash, "esc", Command::CloseTrash),
+    (Context::Trash, "q", Command::CloseTrash),
+    (Context::Trash, "f1", Command::ToggleHelp),
+    (Context::Purge, "y", Command::ConfirmPurge),
+    (Context::Purge, "Y", Command::ConfirmPurge),
+    (Context::Purge, "n", Command::CancelPurge),
+    (Context::Purge, "N", Command::CancelPurge),
+    (Context::Purge, "esc", Command::CancelPurge),
+    (Context::Purge, "f1", Command::ToggleHelp),
+    (Context::BulkTag, "enter", Command::ApplyBulkTag),
+    (Context::BulkTag, "esc", Command::CancelBulkTag),
+    (Context::BulkTag, "f1", Command::ToggleHelp),
+    (Context::MasterPassword, "tab", Command::NextMasterField),
+    (
+        Context::MasterPassword,
+        "enter",
+        Command::SaveMasterPassword,
+    ),
+    (
+        Context::MasterPassword,
+        "esc",
+        Command::CancelMasterPassword,
+    ),
+    (Context::MasterPassword, "f1", Command::ToggleHelp),
+];
+
+type Overrides = &'static [(Context, Command, &'static [&'static str])];
+
+/// What the vim preset changes on top of [`DEFAULT_BINDINGS`].
+const VIM_OVERRIDES: Overrides = &[
+    (Context::List, Command::SelectFirst, &["g g", "home"]),
+    (Context::List, Command::SelectLast, &["G", "end"]),
+    (Context::List, Command::TryDelete, &["d d"]),
+    (Context::List, Command::Edit, &["i", "e"]),
+    (Context::List, Command::NewItem, &["o"]),
+    (Context::List, Command::Quit, &["q", "ctrl-c", "ctrl-C"]),
+];
+
+/// What the emacs preset changes on top of [`DEFAULT_BINDINGS`].
+const EMACS_OVERRIDES: Overrides = &[
+    (Context::List, Command::SelectNext, &["ctrl-n", "down"]),
+    (Context::List, Command::SelectPrevious, &["ctrl-p", "up"]),
+    (Context::List, Command::SelectFirst, &["alt-<", "home"]),
+    (Context::List, Command::SelectLast, &["alt->", "end"]),
+    (Context::List, Command::Search, &["ctrl-s", "/"]),
+    (Context::List, Command::Quit, &["ctrl-x ctrl-c", "ctrl-g"]),
+    (Context::Edit, Command::SaveEdit, &["ctrl-x ctrl-s"]),
+    (Context::Edit, Command::CancelEdit, &["ctrl-g", "esc"]),
+    (
+        Context::Search,
+        Command::ExitSearch,
+        &["ctrl-g", "esc", "tab"],
+    ),
+    (
+        Context::Delete,
+        Command::CancelDelete,
+        &["ctrl-g", "n", "esc"],
+    ),
+    (
+        Context::Discard,
+        Command::CancelDiscard,
+        &["ctrl-g", "n", "esc"],
+    ),
+    (
+        Context::Purge,
+        Command::CancelPurge,
+        &["ctrl-g", "n", "esc"],
+    ),
+    (Context::BulkTag, Command::CancelBulkTag, &["ctrl-g", "esc"]),
+    (
+        Context::MasterPassword,
+        Command::CancelMasterPassword,
+        &["ctrl-g", "esc"],
+    ),
+];
+
+/// The `[keymap]` section of the config file.
+///
+/// ```toml
+/// [keymap]
+/// preset = "vim"
+///
+/// [keymap.bindings.list]
+/// select_next = ["j", "ctrl-n"]
+/// select_first = ["g g"]
+/// ```
+///
+/// Listing a command replaces every binding of it in that context; an empty
+/// list unbinds it. A key that is also the start of a longer sequence in the
+/// same context, like `g` next to `g g`, is rejected.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct KeymapConfig {
+    pub preset: Preset,
+    pub bindings: BTreeMap<Context, BTreeMap<Command, Vec<String>>>,
+}
+
+/// What a sequence of key presses resolves to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Command(Command),
+    /// The keys are the start of a longer binding.
+    Pending,
+    Unbound,
+}
+
+/// Key bindings, shared by the handler and the help/footer rendering.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    pub bindings: Vec<KeyBinding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::from_preset(Preset::Default)
+    }
+}
+
+impl Keymap {
+    pub fn from_preset(preset: Preset) -> Self {
+        let mut keymap = Self {
+            bindings: DEFAULT_BINDINGS
+                .iter()
+                .map(|(context, keys, command)| KeyBinding {
+                    context: *context,
+                    keys: parse_sequence(keys).expect("invalid built-in key binding"),
+                    command: *command,
+                })
+                .collect(),
+        };
+        let overrides = match preset {
+            Preset::Default => &[][..],
+            Preset::Vim => VIM_OVERRIDES,
+            Preset::Emacs => EMACS_OVERRIDES,
+        };
+        for (context, command, keys) in overrides {
+            let keys = keys
+                .iter()
+                .map(|k| parse_sequence(k).expect("invalid built-in key binding"))
+                .collect();
+            keymap.rebind(*context, *command, keys);
+        }
+        keymap
+    }
+
+    pub fn from_config(config: &KeymapConfig) -> AppResult<Self> {
+        let mut keymap = Self::from_preset(config.preset);
+        for (context, commands) in &config.bindings {
+            for (command, keys) in commands {
+                let keys = keys
+                    .iter()
+                    .map(|k| parse_sequence(k))
+                    .collect::<AppResult<Vec<_>>>()?;
+                keymap.rebind(*context, *command, keys);
+            }
+        }
+        keymap.check_conflicts()?;
+        Ok(keymap)
+    }
+
+    /// Reject a binding that is the start of a longer one in the same context:
+    /// [`Keymap::resolve`] would always run it and the longer one never.
+    fn check_conflicts(&self) -> AppResult<()> {
+        for short in &self.bindings {
+            if let Some(long) = self.bindings.iter().find(|b| {
+                b.context == short.context
+                    && b.keys.len() > short.keys.len()
+                    && b.keys.starts_with(&short.keys)
+            }) {
+                return Err(format!(
+                    "Key `{}` ({}) hides `{}` ({}) in the {} keymap",
+                    sequence_label(&short.keys),
+                    short.command.description(),
+                    sequence_label(&long.keys),
+                    long.command.description(),
+                    short.context.title()
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace every binding of `command` in `context` with `keys`.
+    ///
+    /// The new bindings take the place of the first old one so the help keeps its order.
+    fn rebind(&mut self, context: Context, command: Command, keys: Vec<Vec<KeyPress>>) {
+        let position = self
+            .bindings
+            .iter()
+            .position(|b| b.context == context && b.command == command)
+            .unwrap_or(self.bindings.len());
+        self.bindings
+            .retain(|b| !(b.context == context && b.command == command));
+        let position = position.min(self.bindings.len());
+        self.bindings.splice(
+            position..position,
+            keys.into_iter().map(|keys| KeyBinding {
+                context,
+                keys,
+                command,
+            }),
+        );
+    }
+
+    /// Resolve the keys pressed so far in `context`.
+    pub fn resolve(&self, context: Context, keys: &[KeyPress]) -> Resolution {
+        let mut pending = false;
+        for binding in self.bindings.iter().filter(|b| b.context == context) {
+            if binding.keys == keys {
+                return Resolution::Command(binding.command);
+            }
+            if binding.keys.starts_with(keys) {
+                pending = true;
+            }
+        }
+        if pending {
+            Resolution::Pending
+        } else {
+            Resolution::Unbound
+        }
+    }
+
+    /// The commands available in `context` with every key bound to each of them,
+    /// in binding order.
+    ///
+    /// Keys that only differ in case (`y`/`Y`) are listed once.
+    pub fn hints(&self, context: Context) -> Vec<(String, Command)> {
+        let mut hints: Vec<(Vec<String>, Command)> = Vec::new();
+        for binding in self.bindings.iter().filter(|b| b.context == context) {
+            let label = sequence_label(&binding.keys);
+            match hints
+                .iter_mut()
+                .find(|(_, command)| *command == binding.command)
+            {
+                Some((labels, _)) => {
+                    if !labels.iter().any(|l| l.eq_ignore_ascii_case(&label)) {
+                        labels.push(label);
+                    }
+                }
+                None => hints.push((vec![label], binding.command)),
+            }
+        }
+        hints
+            .into_iter()
+            .map(|(labels, command)| (labels.join("/"), command))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyPress {
+        KeyPress::from(KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn test_resolve() {
+        let keymap = Keymap::default();
+        let j = press(KeyCode::Char('j'), KeyModifiers::NONE);
+        assert_eq!(
+            keymap.resolve(Context::List, &[j]),
+            Resolution::Command(Command::SelectNext)
+        );
+        assert_eq!(keymap.resolve(Context::Edit, &[j]), Resolution::Unbound);
+
+        let y = press(KeyCode::Char('Y'), KeyModifiers::SHIFT);
+        assert_eq!(
+            keymap.resolve(Context::Delete, &[y]),
+            Resolution::Command(Command::ConfirmDelete)
+        );
+
+        let c = press(KeyCode::Char('c'), KeyModifiers::NONE);
+        assert_eq!(keymap.resolve(Context::List, &[c]), Resolution::Unbound);
+        let ctrl_c = press(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(
+            keymap.resolve(Context::List, &[ctrl_c]),
+            Resolution::Command(Command::Quit)
+        );
+    }
+
+    #[test]
+    fn test_sequences() {
+        let keymap = Keymap::from_preset(Preset::Vim);
+        let g = press(KeyCode::Char('g'), KeyModifiers::NONE);
+        let big_g = press(KeyCode::Char('G'), KeyModifiers::SHIFT);
+        assert_eq!(keymap.resolve(Context::List, &[g]), Resolution::Pending);
+        assert_eq!(
+            keymap.resolve(Context::List, &[g, g]),
+            Resolution::Command(Command::SelectFirst)
+        );
+        assert_eq!(
+            keymap.resolve(Context::List, &[big_g]),
+            Resolution::Command(Command::SelectLast)
+        );
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(
+            KeyPress::parse("ctrl-x").unwrap(),
+            press(KeyCode::Char('x'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            KeyPress::parse("shift-tab").unwrap(),
+            press(KeyCode::BackTab, KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyPress::parse("shift-a").unwrap(),
+            press(KeyCode::Char('A'), KeyModifiers::SHIFT)
+        );
+        assert_eq!(
+            KeyPress::parse("F5").unwrap(),
+            press(KeyCode::F(5), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyPress::parse("-").unwrap(),
+            press(KeyCode::Char('-'), KeyModifiers::NONE)
+        );
+        assert!(KeyPress::parse("hyper").is_err());
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config: KeymapConfig = toml::from_str(
+            r#"
+            preset = "emacs"
+            [bindings.list]
+            visit = ["o", "enter"]
+            try_delete = []
+            "#,
+        )
+        .unwrap();
+        let keymap = Keymap::from_config(&config).unwrap();
+        let o = press(KeyCode::Char('o'), KeyModifiers::NONE);
+        let d = press(KeyCode::Char('d'), KeyModifiers::NONE);
+        let ctrl_n = press(KeyCode::Char('n'), KeyModifiers::CONTROL);
+        assert_eq!(
+            keymap.resolve(Context::List, &[o]),
+            Resolution::Command(Command::Visit)
+        );
+        assert_eq!(keymap.resolve(Context::List, &[d]), Resolution::Unbound);
+        assert_eq!(
+            keymap.resolve(Context::List, &[ctrl_n]),
+            Resolution::Command(Command::SelectNext)
+        );
+
+        let config: KeymapConfig = toml::from_str(
+            r#"
+            preset = "vim"
+            [bindings.list]
+            edit = ["g"]
+            "#,
+        )
+        .unwrap();
+        let err = Keymap::from_config(&config).unwrap_err().to_string();
+        assert!(err.contains("`g g`"), "{}", err);
+        for preset in [Preset::Default, Preset::Vim, Preset::Emacs] {
+            assert!(Keymap::from_preset(preset).check_conflicts().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_hints() {
+        let keymap = Keymap::default();
+        let hints = keymap.hints(Context::Delete);
+        assert_eq!(
+            hints,
+            vec![
+                ("y".to_string(), Command::ConfirmDelete),
+                ("n/Esc".to_string(), Command::CancelDelete),
+                ("F1".to_string(), Command::ToggleHelp),
+            ]
+        );
+    }
+}
@@ -13,4 +13,31 @@ pub mod tui;
 /// Event handler.
 pub mod handler;
 
+/// Key bindings.
+pub mod keymap;
+
+/// User configuration.
+pub mod config;
+
+/// Edit form values.
+pub mod form;
+
+/// Copying to the system clipboard.
+pub mod clipboard;
+
+/// Exporting accounts to CSV.
+pub mod export;
+
+/// Item types and their fields.
+pub mod item;
+
+/// Passwords fetched on demand.
+pub mod secret;
+
+/// Password strength estimates.
+pub mod strength;
+
+/// Login attempt backoff and keyboard hints.
+pub mod login;
+
 pub mod tcp;
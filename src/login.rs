@@ -0,0 +1,88 @@
+use std::time::{Duration, Instant};
+
+/// Wrong passwords in a row allowed before the next attempt has to wait.
+pub const FREE_ATTEMPTS: u32 = 3;
+
+/// The longest wait between two attempts.
+pub const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Slows down guessing: after [`FREE_ATTEMPTS`] wrong passwords, every further
+/// one doubles the wait before the next attempt, up to [`MAX_DELAY`].
+#[derive(Debug, Clone, Default)]
+pub struct Backoff {
+    /// Wrong passwords in a row.
+    pub failures: u32,
+    /// When the next attempt is allowed.
+    until: Option<Instant>,
+}
+
+impl Backoff {
+    /// How long to wait after the `failures`th wrong password in a row.
+    pub fn delay(failures: u32) -> Duration {
+        match failures.checked_sub(FREE_ATTEMPTS) {
+            None => Duration::ZERO,
+            Some(extra) => Duration::from_secs(1 << extra.min(6)).min(MAX_DELAY),
+        }
+    }
+
+    /// Count a wrong password entered at `now`.
+    pub fn fail(&mut self, now: Instant) {
+        self.failures += 1;
+        let delay = Self::delay(self.failures);
+        self.until = (!delay.is_zero()).then(|| now + delay);
+    }
+
+    /// How long until the next attempt is allowed, `None` if it is now.
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.until
+            .and_then(|until| until.checked_duration_since(now))
+            .filter(|remaining| !remaining.is_zero())
+    }
+}
+
+/// Why the typed password may not be the one meant, if anything looks off.
+///
+/// Terminals do not tell whether Caps Lock is on, so this guesses from the
+/// password alone: letters all in capitals, or characters off the ASCII layout.
+pub fn keyboard_hint(password: &str) -> Option<&'static str> {
+    let letters: Vec<char> = password.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.len() > 1 && letters.iter().all(|c| c.is_uppercase()) {
+        Some("All letters are capitals, is Caps Lock on?")
+    } else if !password.is_ascii() {
+        Some("Non-ASCII characters typed, check the keyboard layout")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(Backoff::delay(FREE_ATTEMPTS - 1), Duration::ZERO);
+        assert_eq!(Backoff::delay(FREE_ATTEMPTS), Duration::from_secs(1));
+        assert_eq!(Backoff::delay(FREE_ATTEMPTS + 2), Duration::from_secs(4));
+        assert_eq!(Backoff::delay(100), MAX_DELAY);
+
+        let now = Instant::now();
+        let mut backoff = Backoff::default();
+        for _ in 1..FREE_ATTEMPTS {
+            backoff.fail(now);
+        }
+        assert_eq!(backoff.remaining(now), None);
+        backoff.fail(now);
+        assert_eq!(backoff.remaining(now), Some(Duration::from_secs(1)));
+        assert_eq!(backoff.remaining(now + Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn test_keyboard_hint() {
+        assert_eq!(keyboard_hint("hunter2"), None);
+        assert_eq!(keyboard_hint("Hunter2"), None);
+        assert_eq!(keyboard_hint("A1"), None);
+        assert!(keyboard_hint("HUNTER2").is_some());
+        assert!(keyboard_hint("hцnter2").is_some());
+    }
+}
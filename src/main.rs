@@ -1,7 +1,10 @@
 use tokio::sync::RwLock;
 use you_should_not_pass_client::app::{App, AppResult};
+use you_should_not_pass_client::config::Config;
 use you_should_not_pass_client::event::{Event, EventHandler};
-use you_should_not_pass_client::handler::handle_key_events;
+use you_should_not_pass_client::handler::{
+    handle_key_events, handle_mouse_events, handle_paste_events,
+};
 use you_should_not_pass_client::tui::Tui;
 use std::io;
 use std::sync::Arc;
@@ -12,26 +15,37 @@ use ratatui::Terminal;
 async fn main() -> AppResult<()> {
     // Create an application.
     // let mut app = App::new();
-    let app = Arc::new(RwLock::new(App::new()));
+    let config = Config::load()?;
+    let app = Arc::new(RwLock::new(App::with_config(&config)?));
 
     // Initialize the terminal user interface.
     let backend = CrosstermBackend::new(io::stderr());
     let terminal = Terminal::new(backend)?;
     let events = EventHandler::new(250);
+    app.write().await.events = Some(events.sender());
     let mut tui = Tui::new(terminal, events);
     tui.init()?;
 
     // Start the main loop.
     while app.read().await.running {
-        // Render the user interface.
-        tui.draw(app.clone())?;
+        // Render the user interface, only when something changed.
+        if app.write().await.take_render_request() {
+            tui.draw(app.clone()).await?;
+        }
         // Handle events.
         match tui.events.next().await? {
-            // Event::Tick => app.tick(),
-            Event::Tick => {},
+            // Ticks only drive timers, which ask for a render themselves.
+            Event::Tick => app.write().await.tick(),
             Event::Key(key_event) => handle_key_events(key_event, app.clone()).await?,
-            Event::Mouse(_) => {}
-            Event::Resize(_, _) => {}
+            Event::Mouse(mouse_event) => handle_mouse_events(mouse_event, app.clone()).await?,
+            Event::Resize(_, _) => app.write().await.request_render(),
+            Event::Paste(text) => handle_paste_events(text, app.clone()).await?,
+            Event::VaultChanged(change) => app.write().await.vault_changed(change).await,
+            Event::DeadLinksChecked(profile, list) => {
+                let mut app = app.write().await;
+                app.dead_links_checked(profile, list);
+                app.request_render();
+            }
         }
     }
 
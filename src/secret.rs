@@ -0,0 +1,232 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    time::{Duration, Instant},
+};
+
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::form::FormValues;
+use crate::tcp::{AccountListItem, CustomField, CustomFieldKind, PasswordHistoryEntry};
+
+/// How long a fetched password is kept before it is fetched again.
+pub const SECRET_TTL: Duration = Duration::from_secs(30);
+
+/// What a list without passwords leaves out of an account: its password, the
+/// secret fields of its type and its old passwords.
+#[derive(Clone, Default)]
+pub struct Secrets {
+    pub password: String,
+    /// The [secret](crate::item::FieldSpec::secret) fields of the type, by key.
+    pub type_fields: BTreeMap<String, String>,
+    /// Old passwords, newest first.
+    pub password_history: Vec<PasswordHistoryEntry>,
+}
+
+/// Never shows the secrets.
+impl fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Secrets").finish_non_exhaustive()
+    }
+}
+
+impl Zeroize for Secrets {
+    fn zeroize(&mut self) {
+        self.password.zeroize();
+        self.type_fields.values_mut().for_each(Zeroize::zeroize);
+        self.password_history.iter_mut().for_each(Zeroize::zeroize);
+    }
+}
+
+impl Zeroize for PasswordHistoryEntry {
+    fn zeroize(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+/// Wipes every secret the item may carry, see [`Secrets`], and the values of
+/// its hidden custom fields.
+impl Zeroize for AccountListItem {
+    fn zeroize(&mut self) {
+        self.password.zeroize();
+        self.type_fields.values_mut().for_each(Zeroize::zeroize);
+        self.password_history.iter_mut().for_each(Zeroize::zeroize);
+        zeroize_hidden(&mut self.custom_fields);
+    }
+}
+
+/// Wipes what the edit form holds of the secrets of the item.
+impl Zeroize for FormValues {
+    fn zeroize(&mut self) {
+        self.password.zeroize();
+        self.type_fields.iter_mut().for_each(Zeroize::zeroize);
+        zeroize_hidden(&mut self.custom_fields);
+    }
+}
+
+/// Hidden custom fields hold secrets too, like PINs and API keys.
+fn zeroize_hidden(fields: &mut [CustomField]) {
+    for field in fields {
+        if field.kind == CustomFieldKind::Hidden {
+            field.value.zeroize();
+        }
+    }
+}
+
+impl Secrets {
+    /// The secrets `item` came with, for lists that have them.
+    pub fn of(item: &AccountListItem) -> Self {
+        let type_fields = item
+            .item_type
+            .fields()
+            .iter()
+            .filter(|spec| spec.secret)
+            .filter_map(|spec| {
+                Some((
+                    spec.key.to_string(),
+                    item.type_fields.get(spec.key)?.clone(),
+                ))
+            })
+            .collect();
+        Self {
+            password: item.password.clone(),
+            type_fields,
+            password_history: item.password_history.clone(),
+        }
+    }
+
+    /// A copy of `item` with these secrets filled in, wiped when dropped.
+    pub fn reveal(&self, item: &AccountListItem) -> Zeroizing<AccountListItem> {
+        let mut item = Zeroizing::new(item.clone());
+        item.password.clone_from(&self.password);
+        for (key, value) in &self.type_fields {
+            item.type_fields.insert(key.clone(), value.clone());
+        }
+        if !self.password_history.is_empty() {
+            item.password_history.clone_from(&self.password_history);
+        }
+        item
+    }
+}
+
+/// Secrets fetched from the server, by account id, wiped from memory when
+/// they expire or are dropped.
+pub struct SecretCache {
+    entries: HashMap<i32, (Zeroizing<Secrets>, Instant)>,
+    ttl: Duration,
+}
+
+impl Default for SecretCache {
+    fn default() -> Self {
+        Self::new(SECRET_TTL)
+    }
+}
+
+/// Shows the ids only, never the passwords.
+impl fmt::Debug for SecretCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.entries.keys()).finish()
+    }
+}
+
+impl SecretCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            ttl,
+        }
+    }
+
+    /// The secrets of the account, unless they were never fetched or expired.
+    pub fn get(&self, id: i32) -> Option<Zeroizing<Secrets>> {
+        self.entries
+            .get(&id)
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < self.ttl)
+            .map(|(secrets, _)| secrets.clone())
+    }
+
+    pub fn insert(&mut self, id: i32, secrets: Zeroizing<Secrets>) {
+        self.entries.insert(id, (secrets, Instant::now()));
+    }
+
+    /// Forget the secrets kept for longer than the time to live.
+    pub fn expire(&mut self) {
+        let ttl = self.ttl;
+        self.entries
+            .retain(|_, (_, fetched_at)| fetched_at.elapsed() < ttl);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::ItemType;
+
+    fn secrets(password: &str) -> Zeroizing<Secrets> {
+        Zeroizing::new(Secrets {
+            password: password.to_string(),
+            ..Secrets::default()
+        })
+    }
+
+    #[test]
+    fn test_secret_cache() {
+        let mut cache = SecretCache::default();
+        cache.insert(1, secrets("hunter2"));
+        assert_eq!(cache.get(1).unwrap().password, "hunter2");
+        assert!(cache.get(2).is_none());
+        assert_eq!(format!("{:?}", cache), "{1}");
+        cache.clear();
+        assert!(cache.get(1).is_none());
+
+        let mut cache = SecretCache::new(Duration::ZERO);
+        cache.insert(1, secrets("hunter2"));
+        assert!(cache.get(1).is_none());
+        cache.expire();
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn test_reveal() {
+        let card = AccountListItem {
+            item_type: ItemType::Card,
+            type_fields: BTreeMap::from([
+                ("cardholder".to_string(), "Ann".to_string()),
+                ("number".to_string(), "4111111111111111".to_string()),
+            ]),
+            custom_fields: vec![
+                CustomField {
+                    name: "PIN".to_string(),
+                    value: "1234".to_string(),
+                    kind: CustomFieldKind::Hidden,
+                },
+                CustomField {
+                    name: "Bank".to_string(),
+                    value: "ACME".to_string(),
+                    kind: CustomFieldKind::Text,
+                },
+            ],
+            ..AccountListItem::default()
+        };
+        let secrets = Secrets::of(&card);
+        assert_eq!(secrets.type_fields.len(), 1);
+        assert_eq!(format!("{:?}", secrets), "Secrets { .. }");
+
+        let mut listed = card.clone();
+        listed.type_fields.remove("number");
+        let mut revealed = secrets.reveal(&listed);
+        assert_eq!(*revealed, card);
+        let mut form = FormValues::from_item(&revealed);
+        revealed.zeroize();
+        assert!(revealed.type_fields.values().all(String::is_empty));
+        assert_eq!(revealed.custom_fields[0].value, "");
+        assert_eq!(revealed.custom_fields[1].value, "ACME");
+        form.zeroize();
+        assert!(form.type_fields.iter().all(String::is_empty));
+        assert_eq!(form.custom_fields[0].value, "");
+    }
+}
@@ -0,0 +1,90 @@
+/// How hard a password is to guess, from a rough estimate of its entropy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Strength {
+    VeryWeak,
+    Weak,
+    Fair,
+    Strong,
+    VeryStrong,
+}
+
+impl Strength {
+    /// Estimate the strength of `password`.
+    ///
+    /// The estimate is the length times the bits of the character classes in
+    /// use, counting repeated characters once past the first few. It does not
+    /// know about dictionary words, so it is an upper bound at best.
+    pub fn of(password: &str) -> Self {
+        let bits = entropy_bits(password);
+        match bits {
+            b if b < 28.0 => Strength::VeryWeak,
+            b if b < 50.0 => Strength::Weak,
+            b if b < 70.0 => Strength::Fair,
+            b if b < 100.0 => Strength::Strong,
+            _ => Strength::VeryStrong,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Strength::VeryWeak => "very weak",
+            Strength::Weak => "weak",
+            Strength::Fair => "fair",
+            Strength::Strong => "strong",
+            Strength::VeryStrong => "very strong",
+        }
+    }
+
+    /// How many of the meter's five cells are filled.
+    pub fn level(&self) -> usize {
+        *self as usize + 1
+    }
+}
+
+fn entropy_bits(password: &str) -> f64 {
+    let mut pool = 0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if password
+        .chars()
+        .any(|c| c.is_ascii_punctuation() || c == ' ')
+    {
+        pool += 33;
+    }
+    if !password.is_ascii() {
+        pool += 100;
+    }
+    if pool == 0 {
+        return 0.0;
+    }
+    let mut unique: Vec<char> = password.chars().collect();
+    unique.sort_unstable();
+    unique.dedup();
+    // `aaaaaaaa` is not eight characters' worth of guessing.
+    let length = password.chars().count().min(unique.len() * 3);
+    length as f64 * (pool as f64).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strength() {
+        assert_eq!(Strength::of(""), Strength::VeryWeak);
+        assert_eq!(Strength::of("hunter2"), Strength::Weak);
+        assert_eq!(Strength::of("aaaaaaaaaaaaaaaaaaaa"), Strength::VeryWeak);
+        assert_eq!(Strength::of("Summer2024"), Strength::Fair);
+        assert_eq!(Strength::of("correct horse"), Strength::Strong);
+        assert_eq!(Strength::of("Tr0ub4dor&3-battery"), Strength::VeryStrong);
+        assert!(Strength::of("abc") < Strength::of("abcdefghij"));
+        assert_eq!(Strength::VeryStrong.level(), 5);
+    }
+}
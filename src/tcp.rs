@@ -1,48 +1,617 @@
 use base64::engine::general_purpose::STANDARD;
 use base64::read::DecoderReader;
 use base64::write::EncoderWriter;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::error::Error;
 use std::io::{Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
 use tokio::net::TcpStream;
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+use crate::config::{Profile, TlsConfig, TransportKind};
+use crate::item::ItemType;
+
+/// The protocol version this client speaks.
+pub const PROTOCOL_VERSION: u32 = 2;
+/// The oldest protocol version a server may speak to work with this client.
+/// Servers from before the handshake count as version 1.
+pub const MIN_SERVER_VERSION: u32 = 1;
+
+/// How long to wait for an answer before giving up on a request.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for the answer to the hello. Servers from before the
+/// handshake may not answer it at all, so this is kept short.
+pub const HELLO_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A byte stream to the server, plain or wrapped in TLS.
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+type BoxStream = Box<dyn Stream>;
 
 pub struct Connect {
-    stream: TcpStream,
+    transport: Transport,
+    /// What the server said about itself in the handshake.
+    pub server: ServerInfo,
+    /// How every message after the handshake is encoded.
+    pub codec: Codec,
+}
+
+impl std::fmt::Debug for Connect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connect")
+            .field("server", &self.server)
+            .field("codec", &self.codec)
+            .field("pipelined", &self.is_pipelined())
+            .finish()
+    }
+}
+
+enum Transport {
+    /// One request at a time, written and then answered. The stream is taken
+    /// out while a request is in flight, see [`Connect::request_serial`].
+    Serial(Mutex<Option<BoxStream>>),
+    /// Requests tagged with ids and answered in any order.
+    Pipelined(Pipeline),
 }
 
 impl Connect {
-    pub async fn new() -> Self {
-        let stream = TcpStream::connect("127.0.0.1:6123").await.unwrap();
-        Connect { stream }
+    /// Connect to the server of `profile` and exchange versions and
+    /// capabilities with it.
+    pub async fn new(profile: &Profile) -> Result<Self, Box<dyn Error>> {
+        let (stream, server, codec) = Self::handshake(profile).await?;
+        let transport =
+            if codec == Codec::Json && server.capabilities.contains(&Capability::Pipelining) {
+                Transport::Pipelined(Pipeline::new(stream))
+            } else {
+                Transport::Serial(Mutex::new(Some(stream)))
+            };
+        Ok(Connect {
+            transport,
+            server,
+            codec,
+        })
     }
 
-    fn get(&self) -> &TcpStream {
-        &self.stream
+    /// Connect to the server of `profile`, known from an earlier connection to
+    /// be from before the handshake, without saying hello again.
+    pub async fn legacy(profile: &Profile) -> Result<Self, Box<dyn Error>> {
+        Ok(Connect {
+            transport: Transport::Serial(Mutex::new(Some(open(profile).await?))),
+            server: ServerInfo::legacy(),
+            codec: Codec::Legacy,
+        })
+    }
+
+    async fn handshake(
+        profile: &Profile,
+    ) -> Result<(BoxStream, ServerInfo, Codec), Box<dyn Error>> {
+        let mut stream = open(profile).await?;
+        let hello = Action::Hello {
+            version: PROTOCOL_VERSION,
+            capabilities: Capability::ALL.to_vec(),
+        };
+        // The hello itself is written the way every server reads it.
+        let answer = tokio::time::timeout(HELLO_TIMEOUT, async {
+            write_request(&mut stream, Codec::Legacy, hello).await?;
+            read_response(&mut stream, Codec::Legacy).await
+        })
+        .await;
+        // The error is not `Send`, so it must not live across the reconnection.
+        let answer = answer.ok().and_then(Result::ok);
+        // Servers from before the handshake do not know the hello: they answer
+        // something else, or nothing, or hang up, and are then reached anew.
+        let (stream, server) = match answer {
+            Some(Ack::Hello(server)) => (stream, server),
+            Some(_) => (stream, ServerInfo::legacy()),
+            None => (open(profile).await?, ServerInfo::legacy()),
+        };
+        server.check_compatible()?;
+        let codec = if server.capabilities.contains(&Capability::Json) {
+            Codec::Json
+        } else {
+            Codec::Legacy
+        };
+        Ok((stream, server, codec))
+    }
+
+    /// Can the features needing `capability` be used with this server?
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.server.capabilities.contains(&capability)
+    }
+
+    /// Can several requests be in flight at once without waiting for each other?
+    pub fn is_pipelined(&self) -> bool {
+        matches!(self.transport, Transport::Pipelined(_))
+    }
+
+    /// Is the connection of no more use, lost or given up after a cancelled request?
+    pub fn is_closed(&self) -> bool {
+        match &self.transport {
+            Transport::Serial(stream) => stream.try_lock().is_ok_and(|stream| stream.is_none()),
+            Transport::Pipelined(pipeline) => pipeline.reader.is_finished(),
+        }
     }
 
     pub async fn request(&self, action: Action) -> Result<Ack, Box<dyn Error>> {
-        let stream = self.get();
-        write_request(stream, action).await?;
-        read_response(stream).await
+        self.request_with_timeout(action, REQUEST_TIMEOUT).await
+    }
+
+    /// Send `action` and wait at most `timeout` for the answer.
+    ///
+    /// Dropping the returned future cancels the request. Requests on the same
+    /// connection wait for each other unless it [`Connect::is_pipelined`].
+    pub async fn request_with_timeout(
+        &self,
+        action: Action,
+        timeout: Duration,
+    ) -> Result<Ack, Box<dyn Error>> {
+        let answer = match &self.transport {
+            Transport::Serial(stream) => {
+                tokio::time::timeout(timeout, Self::request_serial(stream, self.codec, action))
+                    .await
+            }
+            Transport::Pipelined(pipeline) => {
+                tokio::time::timeout(timeout, pipeline.request(action)).await
+            }
+        };
+        match answer {
+            Ok(answer) => answer,
+            Err(_) => Err("The server did not answer in time".into()),
+        }
+    }
+
+    /// The answer to a request cancelled halfway would be read as the answer
+    /// to the next one, so the stream is only put back once it is answered.
+    async fn request_serial(
+        stream: &Mutex<Option<BoxStream>>,
+        codec: Codec,
+        action: Action,
+    ) -> Result<Ack, Box<dyn Error>> {
+        let mut stream = stream.lock().await;
+        let Some(mut taken) = stream.take() else {
+            return Err("The connection was given up after a cancelled request".into());
+        };
+        write_request(&mut taken, codec, action).await?;
+        let ack = read_response(&mut taken, codec).await?;
+        *stream = Some(taken);
+        Ok(ack)
+    }
+
+    /// Open a connection the server of `profile` pushes the vault changes on.
+    pub async fn subscribe(profile: &Profile) -> Result<Subscription, Box<dyn Error>> {
+        let (mut stream, _, codec) = Self::handshake(profile).await?;
+        write_request(&mut stream, codec, Action::Subscribe).await?;
+        match read_response(&mut stream, codec).await? {
+            Ack::Ack => Ok(Subscription {
+                stream,
+                codec,
+                buffer: Vec::new(),
+            }),
+            _ => Err("The server refused the subscription".into()),
+        }
+    }
+}
+
+/// Reach the server of `profile`, over TLS if it asks for it.
+async fn open(profile: &Profile) -> Result<BoxStream, Box<dyn Error>> {
+    let stream = TcpStream::connect(&profile.address)
+        .await
+        .map_err(|e| format!("Cannot reach {}: {}", profile.address, e))?;
+    match profile.transport {
+        TransportKind::Tcp => Ok(Box::new(stream)),
+        TransportKind::Tls => {
+            let name = ServerName::try_from(profile.server_name().to_string())?;
+            let connector = tls_connector(&profile.tls)?;
+            let stream = connector
+                .connect(name, stream)
+                .await
+                .map_err(|e| format!("TLS handshake with {} failed: {}", profile.address, e))?;
+            Ok(Box::new(stream))
+        }
+    }
+}
+
+/// Trust the certificates of `tls.ca_file`, or the usual web roots without one.
+fn tls_connector(tls: &TlsConfig) -> Result<TlsConnector, Box<dyn Error>> {
+    let mut roots = RootCertStore::empty();
+    match &tls.ca_file {
+        Some(path) => {
+            let pem = std::fs::read(path)
+                .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+            for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+                roots.add(cert?)?;
+            }
+            if roots.is_empty() {
+                return Err(format!("No certificate in {}", path.display()).into());
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// A message on a pipelined connection, tagged with the id of its request:
+/// `{"id":3,"body":{"action":"get_info"}}`. The body is not flattened into it
+/// since actions have an `id` of their own.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Envelope<T> {
+    id: u64,
+    body: T,
+}
+
+/// Requests waiting for their answer, by id.
+type Pending = HashMap<u64, oneshot::Sender<Result<Ack, String>>>;
+
+/// The state shared by a pipelined connection and the task reading its answers.
+struct Shared {
+    writer: Mutex<WriteHalf<BoxStream>>,
+    pending: std::sync::Mutex<Pending>,
+    next_id: AtomicU64,
+}
+
+impl Shared {
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    async fn send(&self, id: u64, action: Action) -> Result<(), Box<dyn Error>> {
+        let line = serde_json::to_string(&Envelope { id, body: action })? + "\n";
+        let mut writer = self.writer.lock().await;
+        writer.write_all(line.as_bytes()).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+/// The pipelined side of a connection: a task reads the answers and hands each
+/// to the request with its id.
+struct Pipeline {
+    shared: Arc<Shared>,
+    reader: JoinHandle<()>,
+}
+
+impl Pipeline {
+    fn new(stream: BoxStream) -> Self {
+        let (reader, writer) = tokio::io::split(stream);
+        let shared = Arc::new(Shared {
+            writer: Mutex::new(writer),
+            pending: std::sync::Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+        });
+        let reader = tokio::spawn(read_answers(reader, shared.clone()));
+        Self { shared, reader }
+    }
+
+    async fn request(&self, action: Action) -> Result<Ack, Box<dyn Error>> {
+        let id = self.shared.next_id();
+        let (sender, receiver) = oneshot::channel();
+        self.shared.pending.lock().unwrap().insert(id, sender);
+        let _in_flight = InFlight {
+            shared: self.shared.clone(),
+            id,
+        };
+        self.shared.send(id, action).await?;
+        let answer = receiver
+            .await
+            .map_err(|_| "The connection to the server was lost")?;
+        Ok(answer?)
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// A request of a pipelined connection, cancelled on the server too if it is
+/// dropped before its answer came.
+struct InFlight {
+    shared: Arc<Shared>,
+    id: u64,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        // Answered requests are no longer pending.
+        if self
+            .shared
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&self.id)
+            .is_none()
+        {
+            return;
+        }
+        let shared = self.shared.clone();
+        let request_id = self.id;
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                let cancel = Action::Cancel { request_id };
+                let _ = shared.send(shared.next_id(), cancel).await;
+            });
+        }
+    }
+}
+
+/// Hand every answer of a pipelined connection to the request waiting for it,
+/// until the connection is lost.
+async fn read_answers(mut reader: ReadHalf<BoxStream>, shared: Arc<Shared>) {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        while let Some(line) = take_line(&mut buffer) {
+            route_answer(&shared.pending, &line);
+        }
+        match reader.read(&mut chunk).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+        }
+    }
+    // Dropping the senders tells whoever still waits that the connection is lost.
+    shared.pending.lock().unwrap().clear();
+}
+
+/// Answers to cancelled requests and lines without an id are dropped.
+fn route_answer(pending: &std::sync::Mutex<Pending>, line: &str) {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(line) else {
+        return;
+    };
+    let Some(id) = value.get("id").and_then(serde_json::Value::as_u64) else {
+        return;
+    };
+    let Some(sender) = pending.lock().unwrap().remove(&id) else {
+        return;
+    };
+    let answer = serde_json::from_value::<Envelope<Ack>>(value)
+        .map(|envelope| envelope.body)
+        .map_err(|e| e.to_string());
+    let _ = sender.send(answer);
+}
+
+/// A change to the vault pushed by the server, made by this or another client.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    /// The revision of the vault after the change.
+    pub revision: u64,
+    /// Ids of the accounts added or changed.
+    pub changed: Vec<i32>,
+    /// Ids of the accounts deleted.
+    pub removed: Vec<i32>,
+}
+
+impl ChangeEvent {
+    /// Read one pushed line: a JSON object with [`Codec::Json`], else
+    /// `revision\tchanged\tremoved` with the ids separated by commas.
+    pub fn decode(codec: Codec, line: &str) -> Result<Self, Box<dyn Error>> {
+        match codec {
+            Codec::Legacy => {
+                let fields: Vec<&str> = line.split('\t').collect();
+                Ok(ChangeEvent {
+                    revision: field(&fields, 0)?.parse::<u64>()?,
+                    changed: decode_ids(fields.get(1).copied().unwrap_or_default())?,
+                    removed: decode_ids(fields.get(2).copied().unwrap_or_default())?,
+                })
+            }
+            Codec::Json => Ok(serde_json::from_str(line)?),
+        }
+    }
+}
+
+/// A connection the server pushes [`ChangeEvent`]s on, one per line.
+pub struct Subscription {
+    stream: BoxStream,
+    codec: Codec,
+    /// What has been read past the last whole line.
+    buffer: Vec<u8>,
+}
+
+impl Subscription {
+    /// Wait for the next change.
+    pub async fn next(&mut self) -> Result<ChangeEvent, Box<dyn Error>> {
+        loop {
+            if let Some(line) = take_line(&mut self.buffer) {
+                if line.is_empty() {
+                    continue;
+                }
+                return ChangeEvent::decode(self.codec, &line);
+            }
+            let mut buffer = [0; 4096];
+            match self.stream.read(&mut buffer).await? {
+                0 => return Err("The server closed the subscription".into()),
+                n => self.buffer.extend_from_slice(&buffer[..n]),
+            }
+        }
+    }
+}
+
+/// Split the first whole line off `buffer`, without its newline.
+fn take_line(buffer: &mut Vec<u8>) -> Option<String> {
+    let end = buffer.iter().position(|b| *b == b'\n')?;
+    let line: Vec<u8> = buffer.drain(..=end).collect();
+    Some(
+        String::from_utf8_lossy(&line[..end])
+            .trim_end_matches('\r')
+            .to_string(),
+    )
+}
+
+/// A protocol feature that a client or server may not have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Tags,
+    Folders,
+    CustomFields,
+    Uris,
+    PasswordHistory,
+    Timestamps,
+    ItemTypes,
+    Trash,
+    /// `CheckDeadLink` takes the ids to check.
+    DeadLinkIds,
+    /// Messages after the handshake can be encoded with [`Codec::Json`].
+    Json,
+    /// The server counts revisions and answers [`Action::GetChangesSince`].
+    DeltaSync,
+    /// The server pushes changes to subscribers, see [`Connect::subscribe`].
+    Push,
+    /// With [`Codec::Json`], requests can be tagged with ids and answered in
+    /// any order, see [`Connect::is_pipelined`].
+    Pipelining,
+    /// Accounts come without their password, which is fetched with
+    /// [`Action::GetWebsiteAccountPassword`] when needed.
+    LazyPasswords,
+    /// The master password can be changed with [`Action::ChangeMasterPassword`].
+    ChangeMasterPassword,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 15] = [
+        Capability::Tags,
+        Capability::Folders,
+        Capability::CustomFields,
+        Capability::Uris,
+        Capability::PasswordHistory,
+        Capability::Timestamps,
+        Capability::ItemTypes,
+        Capability::Trash,
+        Capability::DeadLinkIds,
+        Capability::Json,
+        Capability::DeltaSync,
+        Capability::Push,
+        Capability::Pipelining,
+        Capability::LazyPasswords,
+        Capability::ChangeMasterPassword,
+    ];
+
+    /// How the capability is named in the handshake.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Tags => "tags",
+            Capability::Folders => "folders",
+            Capability::CustomFields => "custom_fields",
+            Capability::Uris => "uris",
+            Capability::PasswordHistory => "password_history",
+            Capability::Timestamps => "timestamps",
+            Capability::ItemTypes => "item_types",
+            Capability::Trash => "trash",
+            Capability::DeadLinkIds => "dead_link_ids",
+            Capability::Json => "json",
+            Capability::DeltaSync => "delta_sync",
+            Capability::Push => "push",
+            Capability::Pipelining => "pipelining",
+            Capability::LazyPasswords => "lazy_passwords",
+            Capability::ChangeMasterPassword => "change_master_password",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+}
+
+/// The server's side of the handshake.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    /// The newest protocol version the server speaks.
+    pub version: u32,
+    /// The oldest protocol version the server still accepts from clients.
+    pub min_version: u32,
+    /// What the server supports that this client knows about.
+    pub capabilities: BTreeSet<Capability>,
+}
+
+impl ServerInfo {
+    /// A server from before the handshake.
+    fn legacy() -> Self {
+        Self {
+            version: 1,
+            min_version: 1,
+            capabilities: BTreeSet::new(),
+        }
+    }
+
+    /// Did the server not answer the hello, being from before the handshake?
+    pub fn is_legacy(&self) -> bool {
+        *self == Self::legacy()
+    }
+
+    /// Can this client talk to the server at all?
+    pub fn check_compatible(&self) -> Result<(), Box<dyn Error>> {
+        if self.version < MIN_SERVER_VERSION {
+            return Err(format!(
+                "Server too old: it speaks protocol {}, this client needs {} or newer",
+                self.version, MIN_SERVER_VERSION
+            )
+            .into());
+        }
+        if self.min_version > PROTOCOL_VERSION {
+            return Err(format!(
+                "Server too new: it needs protocol {} or newer, this client speaks {}",
+                self.min_version, PROTOCOL_VERSION
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// The protocol version both sides speak.
+    pub fn negotiated_version(&self) -> u32 {
+        self.version.min(PROTOCOL_VERSION)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
 pub enum Action {
     CheckIdentity {
         password: String,
     },
+    /// Re-key the vault with a new master password. Answered with
+    /// [`Ack::IdentityError`] if `old_password` is wrong.
+    ChangeMasterPassword {
+        old_password: String,
+        new_password: String,
+    },
     // user_account
     GetInfo,
     // website_account
-    // GetWebsiteAccountPassword {
-    //     website_id: i32,
-    // },
+    /// The password of one account, for servers that leave it out of the list.
+    GetWebsiteAccountPassword {
+        website_id: i32,
+    },
     AddWebsiteAccount {
         account: String,
         password: String,
         site_url: String,
         site_name: Option<String>,
         note: Option<String>,
+        tags: Vec<String>,
+        folder: Option<String>,
+        custom_fields: Vec<CustomField>,
+        uris: Vec<AccountUri>,
+        item_type: ItemType,
+        type_fields: BTreeMap<String, String>,
     },
     ChangeWebsiteAccount {
         id: i32,
@@ -51,15 +620,266 @@ pub enum Action {
         new_site_name: Option<String>,
         new_site_url: String,
         new_note: Option<String>,
+        new_tags: Vec<String>,
+        new_folder: Option<String>,
+        new_custom_fields: Vec<CustomField>,
+        new_uris: Vec<AccountUri>,
+        new_item_type: ItemType,
+        new_type_fields: BTreeMap<String, String>,
     },
+    /// Move the account to the trash.
     DeleteWebsiteAccount {
         website_id: i32,
     },
     // check_dead_link
-    CheckDeadLink,
+    /// Check the sites of the accounts with these ids, or of every account when empty.
+    CheckDeadLink {
+        ids: Vec<i32>,
+    },
+    /// The account was just used (copied or visited).
+    TouchWebsiteAccount {
+        id: i32,
+    },
+    // trash
+    /// The accounts in the trash, answered like [`Action::GetInfo`].
+    GetTrash,
+    /// Take the account out of the trash.
+    RestoreWebsiteAccount {
+        id: i32,
+    },
+    /// Delete an account in the trash for good.
+    PurgeWebsiteAccount {
+        id: i32,
+    },
+    /// Delete every account that went to the trash before `deleted_before`.
+    PurgeTrash {
+        deleted_before: DateTime<Utc>,
+    },
+    /// The handshake, sent first on every connection.
+    Hello {
+        version: u32,
+        capabilities: Vec<Capability>,
+    },
+    // delta_sync
+    /// What changed in the vault after `revision`, or everything for 0.
+    GetChangesSince {
+        revision: u64,
+    },
+    // push
+    /// Keep the connection open for the server to push [`ChangeEvent`]s on.
+    Subscribe,
+    // pipelining
+    /// Stop working on the request with this id, nobody waits for its answer.
+    Cancel {
+        request_id: u64,
+    },
+}
+
+/// What a [`CustomField`] holds, which decides how it is shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomFieldKind {
+    #[default]
+    Text,
+    /// Masked like a password.
+    Hidden,
+    Url,
+    /// `true` or `false`.
+    Boolean,
+}
+
+impl CustomFieldKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CustomFieldKind::Text => "text",
+            CustomFieldKind::Hidden => "hidden",
+            CustomFieldKind::Url => "URL",
+            CustomFieldKind::Boolean => "yes/no",
+        }
+    }
+
+    /// The kind after this one, to switch between them from the edit form.
+    pub fn next(&self) -> Self {
+        match self {
+            CustomFieldKind::Text => CustomFieldKind::Hidden,
+            CustomFieldKind::Hidden => CustomFieldKind::Url,
+            CustomFieldKind::Url => CustomFieldKind::Boolean,
+            CustomFieldKind::Boolean => CustomFieldKind::Text,
+        }
+    }
+
+    fn code(&self) -> i32 {
+        match self {
+            CustomFieldKind::Text => 0,
+            CustomFieldKind::Hidden => 1,
+            CustomFieldKind::Url => 2,
+            CustomFieldKind::Boolean => 3,
+        }
+    }
+
+    fn from_code(code: i32) -> Result<Self, Box<dyn Error>> {
+        match code {
+            0 => Ok(CustomFieldKind::Text),
+            1 => Ok(CustomFieldKind::Hidden),
+            2 => Ok(CustomFieldKind::Url),
+            3 => Ok(CustomFieldKind::Boolean),
+            _ => Err("Invalid custom field kind".into()),
+        }
+    }
+}
+
+/// Extra named data of an account, e.g. a security question or a PIN.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomField {
+    pub name: String,
+    pub value: String,
+    pub kind: CustomFieldKind,
+}
+
+/// How a URL of an account is compared with a URL being looked up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UriMatch {
+    /// Same registrable domain, e.g. `mail.example.com` and `www.example.com`.
+    #[default]
+    BaseDomain,
+    /// Same host and port.
+    Host,
+    StartsWith,
+    Exact,
+    /// The account URL is a regular expression.
+    Regex,
+}
+
+impl UriMatch {
+    pub const ALL: [UriMatch; 5] = [
+        UriMatch::BaseDomain,
+        UriMatch::Host,
+        UriMatch::StartsWith,
+        UriMatch::Exact,
+        UriMatch::Regex,
+    ];
+
+    /// How the rule is written in front of a URL in the edit form.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            UriMatch::BaseDomain => "domain",
+            UriMatch::Host => "host",
+            UriMatch::StartsWith => "starts-with",
+            UriMatch::Exact => "exact",
+            UriMatch::Regex => "regex",
+        }
+    }
+
+    fn code(&self) -> i32 {
+        match self {
+            UriMatch::BaseDomain => 0,
+            UriMatch::Host => 1,
+            UriMatch::StartsWith => 2,
+            UriMatch::Exact => 3,
+            UriMatch::Regex => 4,
+        }
+    }
+
+    fn from_code(code: i32) -> Result<Self, Box<dyn Error>> {
+        UriMatch::ALL
+            .into_iter()
+            .find(|rule| rule.code() == code)
+            .ok_or_else(|| "Invalid URL match rule".into())
+    }
+}
+
+/// One of the URLs of an account with the rule used to match it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountUri {
+    pub uri: String,
+    pub rule: UriMatch,
+}
+
+impl AccountUri {
+    /// Read a line of the edit form: a URL, optionally led by a rule keyword,
+    /// e.g. `host https://example.com`.
+    pub fn parse_line(line: &str) -> Self {
+        let line = line.trim();
+        if let Some((keyword, rest)) = line.split_once(char::is_whitespace) {
+            if let Some(rule) = UriMatch::ALL.into_iter().find(|r| r.keyword() == keyword) {
+                return Self {
+                    uri: rest.trim().to_string(),
+                    rule,
+                };
+            }
+        }
+        Self {
+            uri: line.to_string(),
+            rule: UriMatch::BaseDomain,
+        }
+    }
+
+    /// The line [`AccountUri::parse_line`] reads back; the default rule is left out.
+    pub fn to_line(&self) -> String {
+        match self.rule {
+            UriMatch::BaseDomain => self.uri.clone(),
+            rule => format!("{} {}", rule.keyword(), self.uri),
+        }
+    }
+
+    /// Can the URL be opened in a browser? Regular expressions cannot.
+    pub fn is_visitable(&self) -> bool {
+        self.rule != UriMatch::Regex
+    }
+
+    /// Does `url` belong to this account URL under its rule?
+    pub fn matches(&self, url: &str) -> bool {
+        match self.rule {
+            UriMatch::BaseDomain => match (host_of(&self.uri), host_of(url)) {
+                (Some(own), Some(other)) => base_domain(&own) == base_domain(&other),
+                _ => false,
+            },
+            UriMatch::Host => match (parse_url(&self.uri), parse_url(url)) {
+                (Some(own), Some(other)) => {
+                    own.host_str() == other.host_str()
+                        && own.port_or_known_default() == other.port_or_known_default()
+                }
+                _ => false,
+            },
+            UriMatch::StartsWith => url.starts_with(&self.uri),
+            UriMatch::Exact => url == self.uri,
+            UriMatch::Regex => Regex::new(&self.uri).is_ok_and(|re| re.is_match(url)),
+        }
+    }
+}
+
+/// Parse a URL, assuming `https://` when the scheme is missing.
+fn parse_url(url: &str) -> Option<url::Url> {
+    let url = url.trim();
+    if url.contains("://") {
+        url::Url::parse(url).ok()
+    } else {
+        url::Url::parse(&format!("https://{}", url)).ok()
+    }
+}
+
+fn host_of(url: &str) -> Option<String> {
+    parse_url(url)?.host_str().map(str::to_lowercase)
+}
+
+/// The last two labels of a host name, the whole of an IP address.
+///
+/// Without a public suffix list `example.co.uk` counts as `co.uk`, which is why
+/// the stricter rules exist.
+fn base_domain(host: &str) -> &str {
+    if host.parse::<std::net::IpAddr>().is_ok() || host.starts_with('[') {
+        return host;
+    }
+    match host.rmatch_indices('.').nth(1) {
+        Some((i, _)) => &host[i + 1..],
+        None => host,
+    }
 }
 
-#[derive(Debug)]
+/// Fields a server leaves out of the JSON encoding take their default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AccountListItem {
     pub id: Option<i32>,
     pub account: String,
@@ -68,12 +888,287 @@ pub struct AccountListItem {
     pub site_name: Option<String>,
     pub note: Option<String>,
     pub is_dead: bool,
+    pub tags: Vec<String>,
+    /// `/` separated folder path, e.g. `Work/Infra`.
+    pub folder: Option<String>,
+    pub custom_fields: Vec<CustomField>,
+    /// Every URL of the account, `site_url` first.
+    pub uris: Vec<AccountUri>,
+    /// Passwords the account had before, newest first.
+    pub password_history: Vec<PasswordHistoryEntry>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub modified_at: Option<DateTime<Utc>>,
+    /// When the password was last copied or the site visited.
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub item_type: ItemType,
+    /// The values of the [`ItemType::fields`] schema, by key.
+    pub type_fields: BTreeMap<String, String>,
+    /// When the account was moved to the trash, `None` outside of it.
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// A password an account used to have.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PasswordHistoryEntry {
+    pub password: String,
+    /// When it was replaced.
+    pub changed_at: DateTime<Utc>,
+}
+
+impl AccountListItem {
+    /// The request storing the item as it is now, `None` if it has no id.
+    pub fn change_action(&self) -> Option<Action> {
+        Some(Action::ChangeWebsiteAccount {
+            id: self.id?,
+            new_account: self.account.clone(),
+            new_password: self.password.clone(),
+            new_site_name: self.site_name.clone(),
+            new_site_url: self.site_url.clone(),
+            new_note: self.note.clone(),
+            new_tags: self.tags.clone(),
+            new_folder: self.folder.clone(),
+            new_custom_fields: self.custom_fields.clone(),
+            new_uris: self.uris.clone(),
+            new_item_type: self.item_type,
+            new_type_fields: self.type_fields.clone(),
+        })
+    }
+
+    /// How the item is shown in the list: `account - site name` for a login,
+    /// the name for the other types.
+    pub fn title(&self) -> String {
+        match self.item_type {
+            ItemType::Login => {
+                let name = self.site_name.as_ref().unwrap_or(&self.site_url);
+                format!("{} - {}", self.account, name)
+            }
+            item_type => match &self.site_name {
+                Some(name) if !name.is_empty() => name.clone(),
+                _ => format!("Untitled {}", item_type.label().to_lowercase()),
+            },
+        }
+    }
+
+    /// The value of the type's field `key`, empty if it is not set.
+    pub fn type_field(&self, key: &str) -> &str {
+        self.type_fields.get(key).map_or("", String::as_str)
+    }
+
+    /// What "copy password" copies: the password of a login, the main secret
+    /// of other types.
+    pub fn secret(&self) -> &str {
+        match self.item_type.secret_field() {
+            Some(key) => self.type_field(key),
+            None => &self.password,
+        }
+    }
+
+    /// Does the item match the search text? Case insensitive.
+    ///
+    /// Words written as `tag:name` require the tag and `url:address` a URL of the
+    /// account matching the address. The rest is looked up in the title and URLs.
+    pub fn matches(&self, search: &str) -> bool {
+        let mut text = Vec::new();
+        for word in search.split_whitespace() {
+            if let Some(tag) = word.strip_prefix("tag:") {
+                if !self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                    return false;
+                }
+            } else if let Some(url) = word.strip_prefix("url:") {
+                if !self.matches_url(url) {
+                    return false;
+                }
+            } else {
+                text.push(word);
+            }
+        }
+        let text = text.join(" ").to_lowercase();
+        self.title().to_lowercase().contains(&text)
+            || self
+                .uris
+                .iter()
+                .any(|uri| uri.uri.to_lowercase().contains(&text))
+    }
+
+    /// Does any URL of the account match `url` under its rule?
+    pub fn matches_url(&self, url: &str) -> bool {
+        self.uris.iter().any(|uri| uri.matches(url))
+    }
+
+    /// How long ago the current password was set, if the server says when.
+    pub fn password_age(&self, now: DateTime<Utc>) -> Option<chrono::Duration> {
+        let set_at = match self.password_history.first() {
+            Some(previous) => previous.changed_at,
+            None => self.created_at?,
+        };
+        Some(now - set_at)
+    }
+
+    /// When an account in the trash is purged if trash is kept for `retention`.
+    pub fn purged_at(&self, retention: chrono::Duration) -> Option<DateTime<Utc>> {
+        self.deleted_at.map(|deleted_at| deleted_at + retention)
+    }
+
+    /// The URLs that can be opened in a browser.
+    pub fn visitable_uris(&self) -> Vec<&AccountUri> {
+        self.uris.iter().filter(|uri| uri.is_visitable()).collect()
+    }
+
+    /// Is the item in `folder` or one of its subfolders?
+    pub fn in_folder(&self, folder: &str) -> bool {
+        self.folder.as_deref().is_some_and(|own| {
+            own == folder
+                || own
+                    .strip_prefix(folder)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+}
+
+/// Which items the list shows.
+#[derive(Debug, Clone, Default)]
+pub struct ListFilter {
+    pub search: Option<String>,
+    pub folder: Option<String>,
+    /// Only items used before, most recently used first.
+    pub recent: bool,
+}
+
+impl ListFilter {
+    pub fn matches(&self, item: &AccountListItem) -> bool {
+        self.search.as_deref().is_none_or(|s| item.matches(s))
+            && self.folder.as_deref().is_none_or(|f| item.in_folder(f))
+            && (!self.recent || item.last_used_at.is_some())
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct AccountList {
     pub list: Vec<AccountListItem>,
     pub selected: usize,
+    /// Ids of the items marked for a bulk operation.
+    pub marked: BTreeSet<i32>,
+    /// The server revision the list is up to date with, if it counts them.
+    pub revision: Option<u64>,
+}
+
+impl AccountList {
+    /// Indexes into [`AccountList::list`] of the items shown for `filter`.
+    pub fn visible(&self, filter: &ListFilter) -> Vec<usize> {
+        let mut visible: Vec<usize> = self
+            .list
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| filter.matches(item))
+            .map(|(i, _)| i)
+            .collect();
+        if filter.recent {
+            visible.sort_by_key(|i| std::cmp::Reverse(self.list[*i].last_used_at));
+        }
+        visible
+    }
+
+    /// Mark the item at `index`, or unmark it if it is marked.
+    pub fn toggle_mark(&mut self, index: usize) {
+        if let Some(id) = self.list.get(index).and_then(|item| item.id) {
+            if !self.marked.remove(&id) {
+                self.marked.insert(id);
+            }
+        }
+    }
+
+    /// Mark every item at `indexes`, or unmark them all if they already are.
+    pub fn toggle_marks(&mut self, indexes: &[usize]) {
+        let ids: Vec<i32> = indexes
+            .iter()
+            .filter_map(|i| self.list.get(*i).and_then(|item| item.id))
+            .collect();
+        if ids.iter().all(|id| self.marked.contains(id)) {
+            for id in &ids {
+                self.marked.remove(id);
+            }
+        } else {
+            self.marked.extend(ids);
+        }
+    }
+
+    /// Replace the whole list, keeping the same item selected if it is still there.
+    pub fn replace(&mut self, list: Vec<AccountListItem>) {
+        let selected = self.selected_id();
+        self.list = list;
+        self.reselect(selected);
+    }
+
+    /// Merge the changes since the last sync: update the changed items in place,
+    /// add the new ones at the end and drop the removed ones.
+    pub fn apply_changes(&mut self, changed: Vec<AccountListItem>, removed: &[i32]) {
+        let selected = self.selected_id();
+        self.list
+            .retain(|item| !item.id.is_some_and(|id| removed.contains(&id)));
+        for item in changed {
+            match self
+                .list
+                .iter_mut()
+                .find(|old| old.id.is_some() && old.id == item.id)
+            {
+                Some(old) => *old = item,
+                None => self.list.push(item),
+            }
+        }
+        self.reselect(selected);
+    }
+
+    /// Flag the items by id as a dead link check found them.
+    pub fn set_dead(&mut self, checked: &[(i32, bool)]) {
+        for (id, is_dead) in checked {
+            if let Some(item) = self.list.iter_mut().find(|item| item.id == Some(*id)) {
+                item.is_dead = *is_dead;
+            }
+        }
+    }
+
+    fn selected_id(&self) -> Option<(i32, usize)> {
+        let id = self.list.get(self.selected)?.id?;
+        Some((id, self.selected))
+    }
+
+    /// Select the item with the id again, or the one now at its old place.
+    fn reselect(&mut self, selected: Option<(i32, usize)>) {
+        self.selected = match selected {
+            Some((id, index)) => self
+                .list
+                .iter()
+                .position(|item| item.id == Some(id))
+                .unwrap_or(index),
+            None => self.selected,
+        }
+        .min(self.list.len().saturating_sub(1));
+        self.prune_marks();
+    }
+
+    /// Forget marks of items that are no longer in the list.
+    pub fn prune_marks(&mut self) {
+        let ids: BTreeSet<i32> = self.list.iter().filter_map(|item| item.id).collect();
+        self.marked.retain(|id| ids.contains(id));
+    }
+
+    /// Every folder path in use, with their parent folders, sorted.
+    pub fn folders(&self) -> Vec<String> {
+        let mut folders = Vec::new();
+        for folder in self.list.iter().filter_map(|item| item.folder.as_deref()) {
+            let mut path = String::new();
+            for part in folder.split('/').filter(|p| !p.is_empty()) {
+                if !path.is_empty() {
+                    path.push('/');
+                }
+                path.push_str(part);
+                folders.push(path.clone());
+            }
+        }
+        folders.sort();
+        folders.dedup();
+        folders
+    }
 }
 
 /// #[derive(Debug)]
@@ -85,23 +1180,69 @@ pub struct AccountList {
 ///     pub site_name: Option<String>,
 ///     pub note: Option<String>,
 /// }
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "ack", rename_all = "snake_case")]
 pub enum Ack {
     Ack,
-    Info { list: Vec<AccountListItem> },
-    DeadLink { list: Vec<(i32, bool)> },
+    Info {
+        list: Vec<AccountListItem>,
+    },
+    DeadLink {
+        list: Vec<(i32, bool)>,
+    },
     IdentityError,
     DbError,
+    /// The answer to [`Action::Hello`].
+    Hello(ServerInfo),
+    /// The answer to [`Action::GetChangesSince`]: the accounts added or changed
+    /// and the ids of those deleted since then, up to `revision`.
+    Changes {
+        revision: u64,
+        changed: Vec<AccountListItem>,
+        removed: Vec<i32>,
+    },
+    /// The server no longer knows what changed since the asked revision.
+    RevisionTooOld,
+    /// The answer to [`Action::GetWebsiteAccountPassword`]: what the list
+    /// leaves out of the account.
+    Password {
+        password: String,
+        /// The secret fields of the item's type, by key.
+        #[serde(default)]
+        type_fields: BTreeMap<String, String>,
+        /// Old passwords, newest first.
+        #[serde(default)]
+        password_history: Vec<PasswordHistoryEntry>,
+    },
 }
 
 /// read the ack from the socket and return a task
 ///
-/// Here is the TCP format:
+/// With [`Codec::Json`] the ack is one line of JSON, tagged by `"ack"`.
+///
+/// Here is the TCP format of [`Codec::Legacy`]:
 /// "ACTION\tOTHER_MESSAGE"
 ///
 /// for example:
 /// - `"0"`
-/// - `"1\nmy_account\tmy_password\tmy_site_url\tmy_site_name\tmy_note"`
+/// - `"1\nid\tmy_account\tmy_password\tmy_site_url\tmy_site_name\tmy_note\tis_alive\tmy_tags\tmy_folder\tmy_custom_fields\tmy_uris\tmy_password_history\tcreated\tmodified\tlast_used\titem_type\tmy_type_fields\tdeleted"`
+///
+/// Every text field is base64 encoded; tags are joined by newlines before encoding.
+/// Custom fields are one `kind\tname\tvalue` line each, URLs one `rule\turl`
+/// line each and old passwords one `unix_seconds\tpassword` line each, with the
+/// text base64 encoded on its own, before the whole is encoded like the other
+/// fields. `my_site_url` repeats the first URL.
+///
+/// The server adds the old password to the history whenever
+/// `ChangeWebsiteAccount` replaces it. Times are unix seconds, empty when unknown.
+///
+/// The item type is an [`ItemType::code`], a login when missing. Type fields are
+/// one `key\tvalue` line each, encoded like the custom fields. Items other than
+/// logins may have an empty account, password and site URL.
+///
+/// `deleted` is only set in the answer to `GetTrash`.
+/// Older servers leave out the fields they do not know about. Servers with
+/// [`Capability::LazyPasswords`] leave `my_password` empty.
 ///
 /// ## Here is the list of action:
 /// > - 0: Ack
@@ -109,21 +1250,150 @@ pub enum Ack {
 /// > - 2: DeadLink
 /// > - 3: IdentityError
 /// > - 4: DbError
+/// > - 5: Hello, `5\nversion\tmin_version\tcapabilities` with the capability
+/// >   names separated by commas. Unknown capabilities are ignored.
+/// > - 6: Changes, `6\nrevision\tremoved` with the removed ids separated by
+/// >   commas, followed by the changed accounts like in `Info`
+/// > - 7: RevisionTooOld
+/// > - 8: Password, `8\npassword\ttype_fields\tpassword_history` encoded
+/// >   like in `Info`; the secret type fields and the history are optional
 ///
-pub async fn read_response(stream: &TcpStream) -> Result<Ack, Box<dyn Error>> {
-    stream.readable().await?;
+pub async fn read_response<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    codec: Codec,
+) -> Result<Ack, Box<dyn Error>> {
+    let mut message = Vec::new();
     let mut buffer = [0; 4096];
-    match stream.try_read(&mut buffer) {
-        Ok(0) => Err("Failed to read from socket".into()),
-        Ok(_) => {
-            let request = String::from_utf8_lossy(&buffer);
-            let request = request.trim_end_matches('\0');
-            let parts: Vec<&str> = request.split('\n').collect();
-            Ok(pack_ack(parts)?)
+    loop {
+        match stream.read(&mut buffer).await? {
+            0 if message.is_empty() => return Err("Failed to read from socket".into()),
+            // The server hung up: what it sent is all there is.
+            0 => break,
+            n => message.extend_from_slice(&buffer[..n]),
+        }
+        match codec {
+            Codec::Json if message.ends_with(b"\n") => break,
+            Codec::Json => {}
+            // The legacy encoding has no end marker. What has arrived so far is
+            // taken once it decodes; an answer cut off between two reads does
+            // not, so the rest is waited for.
+            Codec::Legacy => {
+                while let Ok(Ok(n @ 1..)) =
+                    tokio::time::timeout(Duration::ZERO, stream.read(&mut buffer)).await
+                {
+                    message.extend_from_slice(&buffer[..n]);
+                }
+                if let Ok(ack) = codec.decode_ack(&String::from_utf8_lossy(&message)) {
+                    return Ok(ack);
+                }
+            }
         }
-        Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => Err("Blocked".into()),
-        Err(e) => Err(e.into()),
     }
+    codec.decode_ack(&String::from_utf8_lossy(&message))
+}
+
+/// How actions and acks are written on the wire, chosen in the handshake.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Codec {
+    /// Tab separated fields with base64 text, see [`read_response`] and
+    /// [`write_request`].
+    #[default]
+    Legacy,
+    /// One JSON object per message, ended by a newline. Actions are tagged by
+    /// `"action"` and acks by `"ack"`, with the variant in snake case.
+    Json,
+}
+
+impl Codec {
+    pub fn encode_action(&self, action: Action) -> Result<String, Box<dyn Error>> {
+        match self {
+            Codec::Legacy => Ok(depack_action(action)),
+            Codec::Json => Ok(serde_json::to_string(&action)? + "\n"),
+        }
+    }
+
+    pub fn decode_ack(&self, message: &str) -> Result<Ack, Box<dyn Error>> {
+        match self {
+            Codec::Legacy => {
+                let message = message.trim_end_matches('\0');
+                pack_ack(message.split('\n').collect())
+            }
+            Codec::Json => Ok(serde_json::from_str(message)?),
+        }
+    }
+}
+
+/// Ids separated by commas.
+fn decode_ids(ids: &str) -> Result<Vec<i32>, Box<dyn Error>> {
+    Ok(ids
+        .split(',')
+        .filter(|id| !id.is_empty())
+        .map(str::parse::<i32>)
+        .collect::<Result<_, _>>()?)
+}
+
+/// One account of an `Info` or `Changes` answer, see [`read_response`].
+fn decode_item(item: &str) -> Result<AccountListItem, Box<dyn Error>> {
+    let item_parts: Vec<&str> = item.split('\t').collect();
+    let id = field(&item_parts, 0)?.parse::<i32>()?;
+    let account = decode(field(&item_parts, 1)?.to_string())?;
+    let password = decode(field(&item_parts, 2)?.to_string())?;
+    let site_url = decode(field(&item_parts, 3)?.to_string())?;
+    let site_name = match field(&item_parts, 4)? {
+        "" => None,
+        site_name => Some(decode(site_name.to_string())?),
+    };
+    let note = match field(&item_parts, 5)? {
+        "" => None,
+        note => Some(decode(note.to_string())?),
+    };
+    let is_dead = field(&item_parts, 6)?.parse::<i32>()? == 0;
+    let tags = decode_tags(item_parts.get(7).copied().unwrap_or_default())?;
+    let folder = match item_parts.get(8) {
+        Some(folder) if !folder.is_empty() => Some(decode(folder.to_string())?),
+        _ => None,
+    };
+    let custom_fields = decode_custom_fields(item_parts.get(9).copied().unwrap_or_default())?;
+    let mut uris = decode_uris(item_parts.get(10).copied().unwrap_or_default())?;
+    if uris.is_empty() {
+        uris.push(AccountUri {
+            uri: site_url.clone(),
+            rule: UriMatch::BaseDomain,
+        });
+    }
+    let password_history =
+        decode_password_history(item_parts.get(11).copied().unwrap_or_default())?;
+    let created_at = decode_time(item_parts.get(12).copied().unwrap_or_default())?;
+    let modified_at = decode_time(item_parts.get(13).copied().unwrap_or_default())?;
+    let last_used_at = decode_time(item_parts.get(14).copied().unwrap_or_default())?;
+    let item_type = match item_parts.get(15) {
+        Some(code) if !code.is_empty() => {
+            ItemType::from_code(code.parse()?).ok_or("Invalid item type")?
+        }
+        _ => ItemType::Login,
+    };
+    let type_fields = decode_type_fields(item_parts.get(16).copied().unwrap_or_default())?;
+    let deleted_at = decode_time(item_parts.get(17).copied().unwrap_or_default())?;
+    Ok(AccountListItem {
+        id: Some(id),
+        account,
+        password,
+        site_url,
+        site_name,
+        note,
+        is_dead,
+        tags,
+        folder,
+        custom_fields,
+        uris,
+        password_history,
+        created_at,
+        modified_at,
+        last_used_at,
+        item_type,
+        type_fields,
+        deleted_at,
+    })
 }
 
 fn pack_ack(parts: Vec<&str>) -> Result<Ack, Box<dyn Error>> {
@@ -132,60 +1402,94 @@ fn pack_ack(parts: Vec<&str>) -> Result<Ack, Box<dyn Error>> {
     match action {
         0 => Ok(Ack::Ack),
         1 => {
-            let mut list = Vec::new();
-            for item in parts.iter().skip(1) {
-                let item_parts: Vec<&str> = item.split('\t').collect();
-                let id = item_parts[0].parse::<i32>()?;
-                let account = decode(item_parts[1].to_string());
-                let password = decode(item_parts[2].to_string());
-                let site_url = decode(item_parts[3].to_string());
-                let site_name = if item_parts[4].is_empty() {
-                    None
-                } else {
-                    Some(decode(item_parts[4].to_string()))
-                };
-                let note = if item_parts[5].is_empty() {
-                    None
-                } else {
-                    Some(decode(item_parts[5].to_string()))
-                };
-                let is_dead = item_parts[6].parse::<i32>()? == 0;
-                list.push(AccountListItem {
-                    id: Some(id),
-                    account,
-                    password,
-                    site_url,
-                    site_name,
-                    note,
-                    is_dead,
-                });
-            }
+            let list = parts
+                .iter()
+                .skip(1)
+                .map(|item| decode_item(item))
+                .collect::<Result<_, _>>()?;
             Ok(Ack::Info { list })
         }
         2 => {
             let mut list = Vec::new();
             for item in parts.iter().skip(1) {
                 let item_parts: Vec<&str> = item.split('\t').collect();
-                let id = item_parts[0].parse::<i32>()?;
-                let is_dead = item_parts[1].parse::<i32>()?;
+                let id = field(&item_parts, 0)?.parse::<i32>()?;
+                let is_dead = field(&item_parts, 1)?.parse::<i32>()?;
                 list.push((id, is_dead == 1))
             }
             Ok(Ack::DeadLink { list })
         }
         3 => Ok(Ack::IdentityError),
         4 => Ok(Ack::DbError),
-        _ => Err("Invalid ack".into()),
+        5 => {
+            let fields: Vec<&str> = parts
+                .get(1)
+                .copied()
+                .unwrap_or_default()
+                .split('\t')
+                .collect();
+            let version = fields[0].parse::<u32>()?;
+            let min_version = match fields.get(1) {
+                Some(min_version) => min_version.parse::<u32>()?,
+                None => version,
+            };
+            let capabilities = fields
+                .get(2)
+                .copied()
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(Capability::from_name)
+                .collect();
+            Ok(Ack::Hello(ServerInfo {
+                version,
+                min_version,
+                capabilities,
+            }))
+        }
+        6 => {
+            let fields: Vec<&str> = field(&parts, 1)?.split('\t').collect();
+            let revision = field(&fields, 0)?.parse::<u64>()?;
+            let removed = decode_ids(fields.get(1).copied().unwrap_or_default())?;
+            let changed = parts
+                .iter()
+                .skip(2)
+                .map(|item| decode_item(item))
+                .collect::<Result<_, _>>()?;
+            Ok(Ack::Changes {
+                revision,
+                changed,
+                removed,
+            })
+        }
+        7 => Ok(Ack::RevisionTooOld),
+        8 => {
+            let fields: Vec<&str> = field(&parts, 1)?.split('\t').collect();
+            Ok(Ack::Password {
+                password: decode(field(&fields, 0)?.to_string())?,
+                type_fields: decode_type_fields(fields.get(1).copied().unwrap_or_default())?,
+                password_history: decode_password_history(
+                    fields.get(2).copied().unwrap_or_default(),
+                )?,
+            })
+        }
+        _ => Err(format!(
+            "Unknown answer {} from the server, it may speak a newer protocol",
+            action
+        )
+        .into()),
     }
 }
 
 /// write the request from the socket and return a task
 ///
-/// Here is the TCP format:
+/// With [`Codec::Json`] the action is one line of JSON, tagged by `"action"`.
+///
+/// Here is the TCP format of [`Codec::Legacy`]:
 /// "ACTION\tOTHER_MESSAGE"
 ///
 /// for example:
 /// - `"CheckIdentity\tmy_password"`
-/// - `"AddWebsiteAccount\tmy_account\tmy_password\tmy_site_url\tmy_site_name\tmy_note"`
+/// - `"AddWebsiteAccount\tmy_account\tmy_password\tmy_site_url\tmy_site_name\tmy_note\tmy_tags\tmy_folder\tmy_custom_fields\tmy_uris\titem_type\tmy_type_fields"`
 ///
 /// ## Here is the list of action:
 /// > - 0: CheckIdentity
@@ -193,16 +1497,30 @@ fn pack_ack(parts: Vec<&str>) -> Result<Ack, Box<dyn Error>> {
 /// > - 2: AddWebsiteAccount
 /// > - 3: ChangeWebsiteAccount
 /// > - 4: DeleteWebsiteAccount
-/// > - 5: CheckDeadLink
+/// > - 5: CheckDeadLink, followed by the ids to check
+/// > - 6: TouchWebsiteAccount
+/// > - 7: GetTrash
+/// > - 8: RestoreWebsiteAccount
+/// > - 9: PurgeWebsiteAccount
+/// > - 10: PurgeTrash, with the time in unix seconds
+/// > - 11: Hello, `11\tversion\tcapabilities` with the capability names
+/// >   separated by commas
+/// > - 12: GetChangesSince, with the revision
+/// > - 13: Subscribe
+/// > - 14: GetWebsiteAccountPassword
+/// > - 15: Cancel, with the request id
+/// > - 16: ChangeMasterPassword, `16\told\tnew` with both base64 encoded
 ///
-pub async fn write_request(stream: &TcpStream, action: Action) -> Result<(), Box<dyn Error>> {
-    let response = depack_action(action);
-    stream.writable().await?;
-    match stream.try_write(response.as_bytes()) {
-        Ok(_) => Ok(()),
-        Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => Err("Blocked".into()),
-        Err(e) => Err(e.into()),
-    }
+pub async fn write_request<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    codec: Codec,
+    action: Action,
+) -> Result<(), Box<dyn Error>> {
+    let request = codec.encode_action(action)?;
+    stream.write_all(request.as_bytes()).await?;
+    // TLS holds writes back until flushed.
+    stream.flush().await?;
+    Ok(())
 }
 
 fn depack_action(action: Action) -> String {
@@ -215,16 +1533,29 @@ fn depack_action(action: Action) -> String {
             site_url,
             site_name,
             note,
+            tags,
+            folder,
+            custom_fields,
+            uris,
+            item_type,
+            type_fields,
         } => {
             let site_name = site_name.unwrap_or("".to_string());
             let note = note.unwrap_or("".to_string());
+            let folder = folder.unwrap_or("".to_string());
             format!(
-                "2\t{}\t{}\t{}\t{}\t{}",
+                "2\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
                 encode(account),
                 encode(password),
                 encode(site_url),
                 encode(site_name),
-                encode(note)
+                encode(note),
+                encode_tags(tags),
+                encode(folder),
+                encode_custom_fields(custom_fields),
+                encode_uris(uris),
+                item_type.code(),
+                encode_type_fields(type_fields)
             )
         }
         Action::ChangeWebsiteAccount {
@@ -234,23 +1565,64 @@ fn depack_action(action: Action) -> String {
             new_site_name,
             new_site_url,
             new_note,
+            new_tags,
+            new_folder,
+            new_custom_fields,
+            new_uris,
+            new_item_type,
+            new_type_fields,
         } => {
             let new_site_name = new_site_name.unwrap_or("".to_string());
             let new_note = new_note.unwrap_or("".to_string());
+            let new_folder = new_folder.unwrap_or("".to_string());
             format!(
-                "3\t{}\t{}\t{}\t{}\t{}\t{}",
+                "3\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
                 id,
                 encode(new_account),
                 encode(new_password),
                 encode(new_site_name),
                 encode(new_site_url),
-                encode(new_note)
+                encode(new_note),
+                encode_tags(new_tags),
+                encode(new_folder),
+                encode_custom_fields(new_custom_fields),
+                encode_uris(new_uris),
+                new_item_type.code(),
+                encode_type_fields(new_type_fields)
             )
         }
         Action::DeleteWebsiteAccount { website_id } => {
             format!("4\t{}", website_id)
         }
-        Action::CheckDeadLink => "5".to_string(),
+        Action::CheckDeadLink { ids } => {
+            let mut request = "5".to_string();
+            for id in ids {
+                request.push_str(&format!("\t{}", id));
+            }
+            request
+        }
+        Action::TouchWebsiteAccount { id } => format!("6\t{}", id),
+        Action::GetTrash => "7".to_string(),
+        Action::RestoreWebsiteAccount { id } => format!("8\t{}", id),
+        Action::PurgeWebsiteAccount { id } => format!("9\t{}", id),
+        Action::PurgeTrash { deleted_before } => {
+            format!("10\t{}", deleted_before.timestamp())
+        }
+        Action::Hello {
+            version,
+            capabilities,
+        } => {
+            let names: Vec<&str> = capabilities.iter().map(Capability::name).collect();
+            format!("11\t{}\t{}", version, names.join(","))
+        }
+        Action::GetChangesSince { revision } => format!("12\t{}", revision),
+        Action::Subscribe => "13".to_string(),
+        Action::GetWebsiteAccountPassword { website_id } => format!("14\t{}", website_id),
+        Action::Cancel { request_id } => format!("15\t{}", request_id),
+        Action::ChangeMasterPassword {
+            old_password,
+            new_password,
+        } => format!("16\t{}\t{}", encode(old_password), encode(new_password)),
     }
 }
 
@@ -262,16 +1634,409 @@ fn encode(data: String) -> String {
     String::from_utf8(str.clone()).unwrap()
 }
 
-fn decode(data: String) -> String {
+/// The tab separated field at `index`, an error if the line is too short.
+fn field<'a>(parts: &[&'a str], index: usize) -> Result<&'a str, Box<dyn Error>> {
+    parts
+        .get(index)
+        .copied()
+        .ok_or_else(|| "Too few fields in the answer".into())
+}
+
+fn encode_tags(tags: Vec<String>) -> String {
+    encode(tags.join("\n"))
+}
+
+fn decode_tags(data: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    Ok(decode(data.to_string())?
+        .split('\n')
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+fn encode_custom_fields(fields: Vec<CustomField>) -> String {
+    let lines: Vec<String> = fields
+        .into_iter()
+        .map(|field| {
+            format!(
+                "{}\t{}\t{}",
+                field.kind.code(),
+                encode(field.name),
+                encode(field.value)
+            )
+        })
+        .collect();
+    encode(lines.join("\n"))
+}
+
+fn decode_custom_fields(data: &str) -> Result<Vec<CustomField>, Box<dyn Error>> {
+    let mut fields = Vec::new();
+    for line in decode(data.to_string())?.split('\n') {
+        if line.is_empty() {
+            continue;
+        }
+        let parts: Vec<&str> = line.split('\t').collect();
+        if parts.len() != 3 {
+            return Err("Invalid custom field".into());
+        }
+        fields.push(CustomField {
+            kind: CustomFieldKind::from_code(parts[0].parse()?)?,
+            name: decode(parts[1].to_string())?,
+            value: decode(parts[2].to_string())?,
+        });
+    }
+    Ok(fields)
+}
+
+fn encode_uris(uris: Vec<AccountUri>) -> String {
+    let lines: Vec<String> = uris
+        .into_iter()
+        .map(|uri| format!("{}\t{}", uri.rule.code(), encode(uri.uri)))
+        .collect();
+    encode(lines.join("\n"))
+}
+
+fn decode_uris(data: &str) -> Result<Vec<AccountUri>, Box<dyn Error>> {
+    let mut uris = Vec::new();
+    for line in decode(data.to_string())?.split('\n') {
+        if line.is_empty() {
+            continue;
+        }
+        let Some((rule, uri)) = line.split_once('\t') else {
+            return Err("Invalid URL".into());
+        };
+        uris.push(AccountUri {
+            uri: decode(uri.to_string())?,
+            rule: UriMatch::from_code(rule.parse()?)?,
+        });
+    }
+    Ok(uris)
+}
+
+fn decode_password_history(data: &str) -> Result<Vec<PasswordHistoryEntry>, Box<dyn Error>> {
+    let mut history = Vec::new();
+    for line in decode(data.to_string())?.split('\n') {
+        if line.is_empty() {
+            continue;
+        }
+        let Some((changed_at, password)) = line.split_once('\t') else {
+            return Err("Invalid password history".into());
+        };
+        history.push(PasswordHistoryEntry {
+            password: decode(password.to_string())?,
+            changed_at: DateTime::from_timestamp(changed_at.parse()?, 0)
+                .ok_or("Invalid password history time")?,
+        });
+    }
+    Ok(history)
+}
+
+fn encode_type_fields(fields: BTreeMap<String, String>) -> String {
+    let lines: Vec<String> = fields
+        .into_iter()
+        .map(|(key, value)| format!("{}\t{}", encode(key), encode(value)))
+        .collect();
+    encode(lines.join("\n"))
+}
+
+fn decode_type_fields(data: &str) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let mut fields = BTreeMap::new();
+    for line in decode(data.to_string())?.split('\n') {
+        if line.is_empty() {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('\t') else {
+            return Err("Invalid item field".into());
+        };
+        fields.insert(decode(key.to_string())?, decode(value.to_string())?);
+    }
+    Ok(fields)
+}
+
+fn decode_time(data: &str) -> Result<Option<DateTime<Utc>>, Box<dyn Error>> {
+    if data.is_empty() {
+        return Ok(None);
+    }
+    let time = DateTime::from_timestamp(data.parse()?, 0).ok_or("Invalid time")?;
+    Ok(Some(time))
+}
+
+fn decode(data: String) -> Result<String, Box<dyn Error>> {
     let mut decoder = DecoderReader::new(data.as_bytes(), &STANDARD);
     let mut decoded = Vec::new();
-    decoder.read_to_end(&mut decoded).unwrap();
-    String::from_utf8(decoded).unwrap()
+    decoder
+        .read_to_end(&mut decoded)
+        .map_err(|e| format!("Invalid base64: {}", e))?;
+    Ok(String::from_utf8(decoded)?)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    fn arb_time() -> impl Strategy<Value = DateTime<Utc>> {
+        (0i64..4_000_000_000).prop_map(|secs| DateTime::from_timestamp(secs, 0).unwrap())
+    }
+
+    fn arb_custom_field() -> impl Strategy<Value = CustomField> {
+        let kind = prop::sample::select(vec![
+            CustomFieldKind::Text,
+            CustomFieldKind::Hidden,
+            CustomFieldKind::Url,
+            CustomFieldKind::Boolean,
+        ]);
+        (any::<String>(), any::<String>(), kind).prop_map(|(name, value, kind)| CustomField {
+            name,
+            value,
+            kind,
+        })
+    }
+
+    fn arb_uri() -> impl Strategy<Value = AccountUri> {
+        (
+            any::<String>(),
+            prop::sample::select(UriMatch::ALL.to_vec()),
+        )
+            .prop_map(|(uri, rule)| AccountUri { uri, rule })
+    }
+
+    /// Every field of an added or changed account, in the order of the action.
+    #[allow(clippy::type_complexity)]
+    fn arb_account() -> impl Strategy<
+        Value = (
+            (String, String, String, Option<String>, Option<String>),
+            (
+                Vec<String>,
+                Option<String>,
+                Vec<CustomField>,
+                Vec<AccountUri>,
+            ),
+            (ItemType, BTreeMap<String, String>),
+        ),
+    > {
+        (
+            (
+                any::<String>(),
+                any::<String>(),
+                any::<String>(),
+                any::<Option<String>>(),
+                any::<Option<String>>(),
+            ),
+            (
+                prop::collection::vec(any::<String>(), 0..4),
+                any::<Option<String>>(),
+                prop::collection::vec(arb_custom_field(), 0..4),
+                prop::collection::vec(arb_uri(), 0..4),
+            ),
+            (
+                prop::sample::select(ItemType::ALL.to_vec()),
+                prop::collection::btree_map(any::<String>(), any::<String>(), 0..4),
+            ),
+        )
+    }
+
+    fn arb_action() -> impl Strategy<Value = Action> {
+        prop_oneof![
+            any::<String>().prop_map(|password| Action::CheckIdentity { password }),
+            Just(Action::GetInfo),
+            arb_account().prop_map(
+                |(
+                    (account, password, site_url, site_name, note),
+                    (tags, folder, custom_fields, uris),
+                    (item_type, type_fields),
+                )| Action::AddWebsiteAccount {
+                    account,
+                    password,
+                    site_url,
+                    site_name,
+                    note,
+                    tags,
+                    folder,
+                    custom_fields,
+                    uris,
+                    item_type,
+                    type_fields,
+                }
+            ),
+            (any::<i32>(), arb_account()).prop_map(
+                |(
+                    id,
+                    (
+                        (account, password, site_url, site_name, note),
+                        (tags, folder, custom_fields, uris),
+                        (item_type, type_fields),
+                    ),
+                )| Action::ChangeWebsiteAccount {
+                    id,
+                    new_account: account,
+                    new_password: password,
+                    new_site_name: site_name,
+                    new_site_url: site_url,
+                    new_note: note,
+                    new_tags: tags,
+                    new_folder: folder,
+                    new_custom_fields: custom_fields,
+                    new_uris: uris,
+                    new_item_type: item_type,
+                    new_type_fields: type_fields,
+                }
+            ),
+            any::<i32>().prop_map(|website_id| Action::DeleteWebsiteAccount { website_id }),
+            prop::collection::vec(any::<i32>(), 0..4).prop_map(|ids| Action::CheckDeadLink { ids }),
+            any::<i32>().prop_map(|id| Action::TouchWebsiteAccount { id }),
+            Just(Action::GetTrash),
+            any::<i32>().prop_map(|id| Action::RestoreWebsiteAccount { id }),
+            any::<i32>().prop_map(|id| Action::PurgeWebsiteAccount { id }),
+            arb_time().prop_map(|deleted_before| Action::PurgeTrash { deleted_before }),
+            (
+                any::<u32>(),
+                prop::collection::vec(prop::sample::select(Capability::ALL.to_vec()), 0..4)
+            )
+                .prop_map(|(version, capabilities)| Action::Hello {
+                    version,
+                    capabilities
+                }),
+            any::<u64>().prop_map(|revision| Action::GetChangesSince { revision }),
+            Just(Action::Subscribe),
+            any::<i32>().prop_map(|website_id| Action::GetWebsiteAccountPassword { website_id }),
+            any::<u64>().prop_map(|request_id| Action::Cancel { request_id }),
+            (any::<String>(), any::<String>()).prop_map(|(old_password, new_password)| {
+                Action::ChangeMasterPassword {
+                    old_password,
+                    new_password,
+                }
+            }),
+        ]
+    }
+
+    fn arb_item() -> impl Strategy<Value = AccountListItem> {
+        (
+            (
+                any::<Option<i32>>(),
+                any::<String>(),
+                any::<String>(),
+                any::<Option<String>>(),
+                any::<bool>(),
+            ),
+            (
+                prop::collection::vec(arb_uri(), 0..3),
+                prop::collection::vec(
+                    (any::<String>(), arb_time()).prop_map(|(password, changed_at)| {
+                        PasswordHistoryEntry {
+                            password,
+                            changed_at,
+                        }
+                    }),
+                    0..3,
+                ),
+                prop::option::of(arb_time()),
+                prop::sample::select(ItemType::ALL.to_vec()),
+            ),
+        )
+            .prop_map(
+                |((id, account, password, note, is_dead), (uris, history, time, item_type))| {
+                    AccountListItem {
+                        id,
+                        account,
+                        password,
+                        note,
+                        is_dead,
+                        uris,
+                        password_history: history,
+                        created_at: time,
+                        deleted_at: time,
+                        item_type,
+                        ..AccountListItem::default()
+                    }
+                },
+            )
+    }
+
+    fn arb_ack() -> impl Strategy<Value = Ack> {
+        prop_oneof![
+            Just(Ack::Ack),
+            prop::collection::vec(arb_item(), 0..3).prop_map(|list| Ack::Info { list }),
+            prop::collection::vec(any::<(i32, bool)>(), 0..4)
+                .prop_map(|list| Ack::DeadLink { list }),
+            Just(Ack::IdentityError),
+            Just(Ack::DbError),
+            (
+                any::<u32>(),
+                any::<u32>(),
+                prop::collection::btree_set(prop::sample::select(Capability::ALL.to_vec()), 0..4)
+            )
+                .prop_map(|(version, min_version, capabilities)| Ack::Hello(
+                    ServerInfo {
+                        version,
+                        min_version,
+                        capabilities,
+                    }
+                )),
+            (
+                any::<u64>(),
+                prop::collection::vec(arb_item(), 0..3),
+                prop::collection::vec(any::<i32>(), 0..4)
+            )
+                .prop_map(|(revision, changed, removed)| Ack::Changes {
+                    revision,
+                    changed,
+                    removed,
+                }),
+            Just(Ack::RevisionTooOld),
+            (
+                any::<String>(),
+                prop::collection::btree_map(any::<String>(), any::<String>(), 0..3)
+            )
+                .prop_map(|(password, type_fields)| Ack::Password {
+                    password,
+                    type_fields,
+                    password_history: Vec::new(),
+                }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn json_action_round_trip(action in arb_action()) {
+            let encoded = Codec::Json.encode_action(action.clone()).unwrap();
+            prop_assert!(encoded.ends_with('\n'));
+            prop_assert_eq!(encoded.matches('\n').count(), 1);
+            let decoded: Action = serde_json::from_str(&encoded).unwrap();
+            prop_assert_eq!(decoded, action);
+        }
+
+        #[test]
+        fn json_ack_round_trip(ack in arb_ack()) {
+            let encoded = serde_json::to_string(&ack).unwrap() + "\n";
+            prop_assert_eq!(Codec::Json.decode_ack(&encoded).unwrap(), ack);
+        }
+
+        #[test]
+        fn envelope_round_trip(id in any::<u64>(), action in arb_action(), ack in arb_ack()) {
+            let action = Envelope { id, body: action };
+            let encoded = serde_json::to_string(&action).unwrap();
+            prop_assert_eq!(serde_json::from_str::<Envelope<Action>>(&encoded).unwrap(), action);
+            let ack = Envelope { id, body: ack };
+            let encoded = serde_json::to_string(&ack).unwrap();
+            prop_assert_eq!(serde_json::from_str::<Envelope<Ack>>(&encoded).unwrap(), ack);
+        }
+
+        #[test]
+        fn legacy_action_is_one_line(action in arb_action()) {
+            // The password to check is the one field written as it is.
+            let raw = matches!(action, Action::CheckIdentity { .. });
+            let encoded = Codec::Legacy.encode_action(action).unwrap();
+            let code = encoded.split('\t').next().unwrap();
+            prop_assert!(code.parse::<u32>().is_ok(), "{}", encoded);
+            prop_assert!(raw || !encoded.contains('\n'), "{}", encoded);
+        }
+
+        #[test]
+        fn legacy_ack_never_panics(message in "[0-9]?[\t\nA-Za-z0-9+/=]{0,64}") {
+            let _ = Codec::Legacy.decode_ack(&message);
+        }
+    }
 
     #[test]
     fn test_depack_action() {
@@ -279,18 +2044,33 @@ mod tests {
             depack_action(Action::CheckIdentity {
                 password: "my_password".to_string()
             }),
-            "CheckIdentity\tmy_password"
+            "0\tmy_password"
         );
-        assert_eq!(depack_action(Action::GetInfo), "Login");
+        assert_eq!(depack_action(Action::GetInfo), "1");
         assert_eq!(
             depack_action(Action::AddWebsiteAccount {
                 account: "my_account".to_string(),
                 password: "my_password".to_string(),
                 site_url: "my_site_url".to_string(),
                 site_name: Some("my_site_name".to_string()),
-                note: Some("my_note".to_string())
+                note: Some("my_note".to_string()),
+                tags: vec!["work".to_string(), "mail".to_string()],
+                folder: Some("Work".to_string()),
+                custom_fields: Vec::new(),
+                uris: Vec::new(),
+                item_type: ItemType::Login,
+                type_fields: BTreeMap::new(),
             }),
-            "AddWebsiteAccount\tmy_account\tmy_password\tmy_site_url\tmy_site_name\tmy_note"
+            format!(
+                "2\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t\t\t0\t",
+                encode("my_account".to_string()),
+                encode("my_password".to_string()),
+                encode("my_site_url".to_string()),
+                encode("my_site_name".to_string()),
+                encode("my_note".to_string()),
+                encode("work\nmail".to_string()),
+                encode("Work".to_string())
+            )
         );
         assert_eq!(
             depack_action(Action::ChangeWebsiteAccount {
@@ -299,35 +2079,337 @@ mod tests {
                 new_password: "my_password".to_string(),
                 new_site_name: Some("my_site_name".to_string()),
                 new_site_url: "my_site_url".to_string(),
-                new_note: Some("my_note".to_string())
+                new_note: Some("my_note".to_string()),
+                new_tags: Vec::new(),
+                new_folder: None,
+                new_custom_fields: Vec::new(),
+                new_uris: Vec::new(),
+                new_item_type: ItemType::Card,
+                new_type_fields: BTreeMap::from([("number".to_string(), "4111".to_string())]),
             }),
-            "ChangeWebsiteAccount\t1\tmy_account\tmy_password\tmy_site_name\tmy_site_url\tmy_note"
+            format!(
+                "3\t1\t{}\t{}\t{}\t{}\t{}\t\t\t\t\t2\t{}",
+                encode("my_account".to_string()),
+                encode("my_password".to_string()),
+                encode("my_site_name".to_string()),
+                encode("my_site_url".to_string()),
+                encode("my_note".to_string()),
+                encode(format!(
+                    "{}\t{}",
+                    encode("number".to_string()),
+                    encode("4111".to_string())
+                ))
+            )
         );
         assert_eq!(
             depack_action(Action::DeleteWebsiteAccount { website_id: 1 }),
-            "DeleteWebsiteAccount\t1"
+            "4\t1"
+        );
+        assert_eq!(depack_action(Action::CheckDeadLink { ids: vec![] }), "5");
+        assert_eq!(
+            depack_action(Action::CheckDeadLink { ids: vec![1, 2] }),
+            "5\t1\t2"
+        );
+        assert_eq!(depack_action(Action::TouchWebsiteAccount { id: 7 }), "6\t7");
+        assert_eq!(depack_action(Action::GetTrash), "7");
+        assert_eq!(
+            depack_action(Action::RestoreWebsiteAccount { id: 7 }),
+            "8\t7"
+        );
+        assert_eq!(depack_action(Action::PurgeWebsiteAccount { id: 7 }), "9\t7");
+        assert_eq!(
+            depack_action(Action::PurgeTrash {
+                deleted_before: DateTime::from_timestamp(1700000000, 0).unwrap()
+            }),
+            "10\t1700000000"
+        );
+        assert_eq!(
+            depack_action(Action::GetChangesSince { revision: 42 }),
+            "12\t42"
+        );
+        assert_eq!(depack_action(Action::Subscribe), "13");
+        assert_eq!(
+            depack_action(Action::GetWebsiteAccountPassword { website_id: 7 }),
+            "14\t7"
+        );
+        assert_eq!(depack_action(Action::Cancel { request_id: 3 }), "15\t3");
+        assert_eq!(
+            depack_action(Action::ChangeMasterPassword {
+                old_password: "a".to_string(),
+                new_password: "b\tc".to_string(),
+            }),
+            format!("16\tYQ==\t{}", encode("b\tc".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_route_answer() {
+        let pending = std::sync::Mutex::new(Pending::new());
+        let (sender, mut first) = oneshot::channel();
+        pending.lock().unwrap().insert(1, sender);
+        let (sender, mut second) = oneshot::channel();
+        pending.lock().unwrap().insert(2, sender);
+
+        route_answer(&pending, r#"{"id":2,"body":{"ack":"ack"}}"#);
+        route_answer(&pending, r#"{"id":9,"body":{"ack":"ack"}}"#);
+        route_answer(&pending, "not json");
+        assert_eq!(second.try_recv().unwrap(), Ok(Ack::Ack));
+        assert!(first.try_recv().is_err());
+
+        route_answer(&pending, r#"{"id":1,"body":{"ack":"nonsense"}}"#);
+        assert!(first.try_recv().unwrap().is_err());
+        assert!(pending.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pipeline() {
+        use tokio::io::AsyncBufReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        // Answers the first two requests in reverse order, then reports what came next.
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = tokio::io::BufReader::new(reader).lines();
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let line = lines.next_line().await.unwrap().unwrap();
+                requests.push(serde_json::from_str::<Envelope<Action>>(&line).unwrap());
+            }
+            for request in requests.iter().rev() {
+                let body = match request.body {
+                    Action::GetTrash => Ack::Info { list: Vec::new() },
+                    _ => Ack::Ack,
+                };
+                let line = serde_json::to_string(&Envelope {
+                    id: request.id,
+                    body,
+                })
+                .unwrap();
+                writer.write_all(line.as_bytes()).await.unwrap();
+                writer.write_all(b"\n").await.unwrap();
+            }
+            let mut rest = Vec::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                rest.push(
+                    serde_json::from_str::<Envelope<Action>>(&line)
+                        .unwrap()
+                        .body,
+                );
+            }
+            rest
+        });
+
+        let pipeline = Pipeline::new(Box::new(TcpStream::connect(address).await.unwrap()));
+        let (info, ack) = tokio::join!(
+            pipeline.request(Action::GetTrash),
+            pipeline.request(Action::GetInfo),
+        );
+        assert_eq!(info.unwrap(), Ack::Info { list: Vec::new() });
+        assert_eq!(ack.unwrap(), Ack::Ack);
+
+        // Never answered: times out and is cancelled on the server.
+        let timed_out =
+            tokio::time::timeout(Duration::from_millis(50), pipeline.request(Action::GetInfo))
+                .await;
+        assert!(timed_out.is_err());
+        assert!(pipeline.shared.pending.lock().unwrap().is_empty());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(pipeline);
+        assert_eq!(
+            server.await.unwrap(),
+            vec![Action::GetInfo, Action::Cancel { request_id: 2 }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_serial_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        // Accepted, never answered.
+        let _peer = listener.accept().await.unwrap();
+        let stream = Mutex::new(Some(Box::new(stream) as BoxStream));
+
+        let request = Connect::request_serial(&stream, Codec::Json, Action::GetInfo);
+        assert!(tokio::time::timeout(Duration::from_millis(50), request)
+            .await
+            .is_err());
+        // Its answer could still come, so the stream is not used again.
+        let next = Connect::request_serial(&stream, Codec::Json, Action::GetInfo).await;
+        assert!(next.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_legacy_in_parts() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let item = format!(
+            "1\t{}\t{}\t{}\t\t\t1",
+            encode("my_account".to_string()),
+            encode("my_password".to_string()),
+            encode("my_site_url".to_string())
+        );
+        let answer = format!("1\n{}", item);
+        let (head, tail) = answer.split_at(answer.len() - 5);
+        let tail = tail.to_string();
+        server.write_all(head.as_bytes()).await.unwrap();
+        let writer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            server.write_all(tail.as_bytes()).await.unwrap();
+            server
+        });
+
+        let Ack::Info { list } = read_response(&mut client, Codec::Legacy).await.unwrap() else {
+            panic!("Info error");
+        };
+        assert_eq!(list[0].site_url, "my_site_url");
+        // A server hanging up halfway leaves an answer that does not decode.
+        let mut server = writer.await.unwrap();
+        server.write_all(head.as_bytes()).await.unwrap();
+        drop(server);
+        assert!(read_response(&mut client, Codec::Legacy).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_fallback() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let profile = Profile {
+            address: listener.local_addr().unwrap().to_string(),
+            ..Profile::default()
+        };
+        // An old server hanging up on the hello, then answering the next connections.
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let _ = stream.read(&mut [0; 64]).await;
+            drop(stream);
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0; 64];
+                let n = stream.read(&mut request).await.unwrap();
+                stream.write_all(b"0").await.unwrap();
+                requests.push(String::from_utf8_lossy(&request[..n]).to_string());
+            }
+            requests
+        });
+
+        let conn = Connect::new(&profile).await.unwrap();
+        assert!(conn.server.is_legacy());
+        assert_eq!(conn.codec, Codec::Legacy);
+        assert_eq!(conn.request(Action::GetInfo).await.unwrap(), Ack::Ack);
+        // Known to be old, the server is not said hello to again.
+        let conn = Connect::legacy(&profile).await.unwrap();
+        assert_eq!(conn.request(Action::GetInfo).await.unwrap(), Ack::Ack);
+        assert_eq!(server.await.unwrap(), ["1", "1"]);
+    }
+
+    #[test]
+    fn test_change_event() {
+        let mut buffer = b"7\t1,2\t\r\n{\"revision\":8,".to_vec();
+        let line = take_line(&mut buffer).unwrap();
+        assert_eq!(
+            ChangeEvent::decode(Codec::Legacy, &line).unwrap(),
+            ChangeEvent {
+                revision: 7,
+                changed: vec![1, 2],
+                removed: Vec::new(),
+            }
+        );
+        assert_eq!(take_line(&mut buffer), None);
+
+        buffer.extend_from_slice(b"\"changed\":[],\"removed\":[3]}\n");
+        let line = take_line(&mut buffer).unwrap();
+        assert_eq!(
+            ChangeEvent::decode(Codec::Json, &line).unwrap(),
+            ChangeEvent {
+                revision: 8,
+                changed: Vec::new(),
+                removed: vec![3],
+            }
         );
-        assert_eq!(depack_action(Action::CheckDeadLink), "CheckDeadLink");
+        assert!(buffer.is_empty());
+        assert!(ChangeEvent::decode(Codec::Legacy, "x\t1").is_err());
     }
 
     #[test]
     fn test_pack_ack() {
-        let parts = vec!["Ack"];
+        let parts = vec!["0"];
         let ack = pack_ack(parts).unwrap();
         if let Ack::Ack = ack {
         } else {
             panic!("Ack error");
         }
 
-        let parts = vec![
-            "Info",
-            "1\tmy_account\tmy_password\tmy_site_url\tmy_site_name\tmy_note\t1",
-            "2\tmy_account\tmy_password\tmy_site_url\tmy_site_name\tmy_note\t1",
-            "3\tmy_account\tmy_password\tmy_site_url\t\t\t0",
+        let custom_fields = vec![
+            CustomField {
+                name: "PIN".to_string(),
+                value: "12\t34".to_string(),
+                kind: CustomFieldKind::Hidden,
+            },
+            CustomField {
+                name: "2FA".to_string(),
+                value: "true".to_string(),
+                kind: CustomFieldKind::Boolean,
+            },
         ];
+        let uris = vec![
+            AccountUri {
+                uri: "my_site_url".to_string(),
+                rule: UriMatch::Host,
+            },
+            AccountUri {
+                uri: "^https://sso\\.".to_string(),
+                rule: UriMatch::Regex,
+            },
+        ];
+        let history = format!(
+            "1700000000\t{}\n1600000000\t{}",
+            encode("older".to_string()),
+            encode("oldest".to_string())
+        );
+        let full = format!(
+            "1\t{}\t{}\t{}\t{}\t{}\t1\t{}\t{}\t{}\t{}\t{}\t1500000000\t1700000000\t",
+            encode("my_account".to_string()),
+            encode("my_password".to_string()),
+            encode("my_site_url".to_string()),
+            encode("my_site_name".to_string()),
+            encode("my_note".to_string()),
+            encode("work\nmail".to_string()),
+            encode("Work/Infra".to_string()),
+            encode_custom_fields(custom_fields.clone()),
+            encode_uris(uris.clone()),
+            encode(history)
+        );
+        // Written by a server without tags and folders.
+        let plain = format!(
+            "2\t{}\t{}\t{}\t{}\t{}\t1",
+            encode("my_account".to_string()),
+            encode("my_password".to_string()),
+            encode("my_site_url".to_string()),
+            encode("my_site_name".to_string()),
+            encode("my_note".to_string())
+        );
+        let old = format!(
+            "3\t{}\t{}\t{}\t\t\t0",
+            encode("my_account".to_string()),
+            encode("my_password".to_string()),
+            encode("my_site_url".to_string())
+        );
+        let card = format!(
+            "4\t\t\t\t{}\t\t1\t\t\t\t\t\t\t\t\t2\t{}\t1700000000",
+            encode("Visa".to_string()),
+            encode(format!(
+                "{}\t{}",
+                encode("number".to_string()),
+                encode("4111111111111111".to_string())
+            ))
+        );
+        let parts = vec!["1", &full, &plain, &old, &card];
         let ack = pack_ack(parts).unwrap();
         if let Ack::Info { list } = ack {
-            assert_eq!(list.len(), 3);
+            assert_eq!(list.len(), 4);
             assert_eq!(list[0].id, Some(1));
             assert_eq!(list[0].account, "my_account");
             assert_eq!(list[0].password, "my_password");
@@ -346,30 +2428,277 @@ mod tests {
             assert_eq!(list[2].site_url, "my_site_url");
             assert_eq!(list[2].site_name, None);
             assert_eq!(list[2].note, None);
+
+            assert_eq!(list[0].tags, vec!["work", "mail"]);
+            assert_eq!(list[0].folder, Some("Work/Infra".to_string()));
+            assert!(list[1].tags.is_empty());
+            assert_eq!(list[1].folder, None);
+
+            assert_eq!(list[0].custom_fields, custom_fields);
+            assert!(list[1].custom_fields.is_empty());
+            assert_eq!(list[0].uris, uris);
+            assert_eq!(list[1].uris[0].uri, "my_site_url");
+            assert_eq!(list[0].password_history.len(), 2);
+            assert_eq!(list[0].password_history[0].password, "older");
+            assert_eq!(
+                list[0].password_history[1].changed_at.timestamp(),
+                1600000000
+            );
+            assert_eq!(list[0].created_at.unwrap().timestamp(), 1500000000);
+            assert_eq!(list[0].modified_at.unwrap().timestamp(), 1700000000);
+            assert_eq!(list[0].last_used_at, None);
+            let now = DateTime::from_timestamp(1700000100, 0).unwrap();
+            assert_eq!(list[0].password_age(now).unwrap().num_seconds(), 100);
+            assert_eq!(list[1].password_age(now), None);
+            assert_eq!(list[1].item_type, ItemType::Login);
+            assert_eq!(list[3].item_type, ItemType::Card);
+            assert_eq!(list[3].title(), "Visa");
+            assert_eq!(list[3].secret(), "4111111111111111");
+            assert_eq!(list[0].deleted_at, None);
+            assert_eq!(
+                list[3]
+                    .purged_at(chrono::Duration::days(1))
+                    .unwrap()
+                    .timestamp(),
+                1700086400
+            );
         } else {
             panic!("Info error");
         }
 
-        let parts = vec!["DeadLink"];
+        let parts = vec!["2", "1\t1", "2\t0"];
         let ack = pack_ack(parts).unwrap();
-        if let Ack::DeadLink { list: _ } = ack {
+        if let Ack::DeadLink { list } = ack {
+            assert_eq!(list, vec![(1, true), (2, false)]);
         } else {
             panic!("DeadLink error");
         }
 
-        let parts = vec!["IdentityError"];
+        let parts = vec!["3"];
         let ack = pack_ack(parts).unwrap();
         if let Ack::IdentityError = ack {
         } else {
             panic!("IdentityError error");
         }
 
-        let parts = vec!["DbError"];
+        let parts = vec!["4"];
         let ack = pack_ack(parts).unwrap();
         if let Ack::DbError = ack {
         } else {
             panic!("DbError error");
         }
+
+        let Ack::Changes {
+            revision,
+            changed,
+            removed,
+        } = pack_ack(vec!["6", "42\t3,4", &old]).unwrap()
+        else {
+            panic!("Changes error");
+        };
+        assert_eq!(revision, 42);
+        assert_eq!(changed[0].id, Some(3));
+        assert_eq!(removed, vec![3, 4]);
+        let Ack::Changes {
+            changed, removed, ..
+        } = pack_ack(vec!["6", "43\t"]).unwrap()
+        else {
+            panic!("Changes error");
+        };
+        assert!(changed.is_empty() && removed.is_empty());
+        assert!(matches!(pack_ack(vec!["7"]).unwrap(), Ack::RevisionTooOld));
+        assert_eq!(
+            pack_ack(vec!["8", &encode("pa\tss".to_string())]).unwrap(),
+            Ack::Password {
+                password: "pa\tss".to_string(),
+                type_fields: BTreeMap::new(),
+                password_history: Vec::new(),
+            }
+        );
+        let card = format!(
+            "{}\t{}",
+            encode(String::new()),
+            encode_type_fields(BTreeMap::from([("cvv".to_string(), "123".to_string())]))
+        );
+        let Ack::Password { type_fields, .. } = pack_ack(vec!["8", &card]).unwrap() else {
+            panic!("Password error");
+        };
+        assert_eq!(type_fields["cvv"], "123");
+        assert!(pack_ack(vec!["8"]).is_err());
+        assert!(pack_ack(vec!["9"]).is_err());
+        // Short lines and broken base64 are errors, not panics.
+        assert!(pack_ack(vec!["1", "1\tYQ=="]).is_err());
+        assert!(pack_ack(vec!["1", "1\t!!\t\t\t\t\t1"]).is_err());
+        assert!(pack_ack(vec!["2", "1"]).is_err());
+    }
+
+    #[test]
+    fn test_handshake() {
+        assert_eq!(
+            depack_action(Action::Hello {
+                version: 2,
+                capabilities: vec![Capability::Tags, Capability::Trash],
+            }),
+            "11\t2\ttags,trash"
+        );
+
+        let Ack::Hello(server) = pack_ack(vec!["5", "3\t2\ttrash,teleport,tags"]).unwrap() else {
+            panic!("Hello error");
+        };
+        assert_eq!(server.version, 3);
+        assert_eq!(
+            server.capabilities,
+            BTreeSet::from([Capability::Tags, Capability::Trash])
+        );
+        assert!(server.check_compatible().is_ok());
+        assert_eq!(server.negotiated_version(), PROTOCOL_VERSION);
+
+        let Ack::Hello(server) = pack_ack(vec!["5", "9\t9"]).unwrap() else {
+            panic!("Hello error");
+        };
+        let error = server.check_compatible().unwrap_err().to_string();
+        assert!(error.starts_with("Server too new"));
+        assert!(server.capabilities.is_empty());
+
+        let too_old = ServerInfo {
+            version: 0,
+            ..ServerInfo::legacy()
+        };
+        let error = too_old.check_compatible().unwrap_err().to_string();
+        assert!(error.starts_with("Server too old"));
+        assert!(ServerInfo::legacy().check_compatible().is_ok());
+    }
+
+    #[test]
+    fn test_filter() {
+        let item = |title: &str, tags: &[&str], folder: Option<&str>| AccountListItem {
+            account: title.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            folder: folder.map(str::to_string),
+            ..AccountListItem::default()
+        };
+        let account_list = AccountList {
+            list: vec![
+                item("mail", &["Work"], Some("Work/Infra")),
+                item("bank", &["money"], Some("Home")),
+                item("chat", &[], Some("Workshop")),
+            ],
+            ..AccountList::default()
+        };
+
+        let search = |search: &str| ListFilter {
+            search: Some(search.to_string()),
+            ..ListFilter::default()
+        };
+        assert_eq!(account_list.visible(&search("tag:work")), vec![0]);
+        assert_eq!(account_list.visible(&search("tag:money ba")), vec![1]);
+        assert_eq!(
+            account_list.visible(&search("tag:money mail")),
+            Vec::<usize>::new()
+        );
+
+        let folder = |folder: &str| ListFilter {
+            folder: Some(folder.to_string()),
+            ..ListFilter::default()
+        };
+        assert_eq!(account_list.visible(&folder("Work")), vec![0]);
+        assert_eq!(account_list.visible(&folder("Work/Infra")), vec![0]);
+        assert_eq!(account_list.visible(&ListFilter::default()), vec![0, 1, 2]);
+
+        let mut account_list = account_list;
+        account_list.list[1].last_used_at = DateTime::from_timestamp(100, 0);
+        account_list.list[2].last_used_at = DateTime::from_timestamp(200, 0);
+        let recent = ListFilter {
+            recent: true,
+            ..ListFilter::default()
+        };
+        assert_eq!(account_list.visible(&recent), vec![2, 1]);
+
+        assert_eq!(
+            account_list.folders(),
+            vec!["Home", "Work", "Work/Infra", "Workshop"]
+        );
+    }
+
+    #[test]
+    fn test_marks() {
+        let item = |id: i32| AccountListItem {
+            id: Some(id),
+            ..AccountListItem::default()
+        };
+        let mut account_list = AccountList {
+            list: vec![item(1), item(2), item(3)],
+            ..AccountList::default()
+        };
+        account_list.toggle_mark(0);
+        assert_eq!(account_list.marked, BTreeSet::from([1]));
+        account_list.toggle_marks(&[0, 1]);
+        assert_eq!(account_list.marked, BTreeSet::from([1, 2]));
+        account_list.toggle_marks(&[0, 1]);
+        assert!(account_list.marked.is_empty());
+
+        account_list.toggle_marks(&[1, 2]);
+        account_list.list.pop();
+        account_list.prune_marks();
+        assert_eq!(account_list.marked, BTreeSet::from([2]));
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let item = |id: i32, account: &str| AccountListItem {
+            id: Some(id),
+            account: account.to_string(),
+            ..AccountListItem::default()
+        };
+        let mut account_list = AccountList {
+            list: vec![item(1, "a"), item(2, "b"), item(3, "c")],
+            selected: 2,
+            ..AccountList::default()
+        };
+        account_list.marked.insert(1);
+        account_list.apply_changes(vec![item(2, "B"), item(4, "d")], &[1]);
+        let accounts: Vec<&str> = account_list
+            .list
+            .iter()
+            .map(|i| i.account.as_str())
+            .collect();
+        assert_eq!(accounts, vec!["B", "c", "d"]);
+        // The selection follows the item, and marks of removed items go.
+        assert_eq!(account_list.selected, 1);
+        assert!(account_list.marked.is_empty());
+
+        // A removed selection stays at the same place.
+        account_list.apply_changes(Vec::new(), &[3]);
+        assert_eq!(account_list.selected, 1);
+        assert_eq!(account_list.list[1].id, Some(4));
+        account_list.apply_changes(Vec::new(), &[4]);
+        assert_eq!(account_list.selected, 0);
+
+        account_list.replace(vec![item(5, "e"), item(2, "b")]);
+        assert_eq!(account_list.selected, 1);
+        account_list.replace(Vec::new());
+        assert_eq!(account_list.selected, 0);
+    }
+
+    #[test]
+    fn test_uri_match() {
+        let uri = |line: &str| AccountUri::parse_line(line);
+
+        assert!(uri("example.com").matches("https://mail.example.com/inbox"));
+        assert!(!uri("example.com").matches("https://example.org"));
+        assert!(uri("host example.com").matches("https://example.com:443/login"));
+        assert!(!uri("host example.com").matches("https://mail.example.com"));
+        assert!(uri("starts-with https://example.com/a").matches("https://example.com/a/b"));
+        assert!(!uri("exact https://example.com/a").matches("https://example.com/a/b"));
+        assert!(uri(r"regex ^https://(sso|login)\.corp\.").matches("https://sso.corp.net"));
+        assert!(!uri("regex (").matches("("));
+
+        assert_eq!(
+            uri("  exact   https://x.com ").to_line(),
+            "exact https://x.com"
+        );
+        assert_eq!(uri("x.com").rule, UriMatch::BaseDomain);
+        assert_eq!(base_domain("127.0.0.1"), "127.0.0.1");
     }
 
     // #[tokio::test]
@@ -1,7 +1,9 @@
 use crate::app::{App, AppResult};
 use crate::event::EventHandler;
 use crate::ui;
-use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::event::{
+    DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+};
 use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
 use ratatui::backend::Backend;
 use ratatui::Terminal;
@@ -30,10 +32,16 @@ impl<B: Backend> Tui<B> {
 
     /// Initializes the terminal interface.
     ///
-    /// It enables the raw mode and sets terminal properties.
+    /// It enables the raw mode and sets terminal properties: mouse capture and
+    /// bracketed paste, so pasted text arrives in one piece instead of as keys.
     pub fn init(&mut self) -> AppResult<()> {
         terminal::enable_raw_mode()?;
-        crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
+        crossterm::execute!(
+            io::stderr(),
+            EnterAlternateScreen,
+            EnableMouseCapture,
+            EnableBracketedPaste
+        )?;
 
         // Define a custom panic hook to reset the terminal properties.
         // This way, you won't have your terminal messed up if an unexpected error happens.
@@ -50,10 +58,13 @@ impl<B: Backend> Tui<B> {
 
     /// [`Draw`] the terminal interface by [`rendering`] the widgets.
     ///
+    /// Waits for the app lock instead of skipping the frame when it is busy.
+    ///
     /// [`Draw`]: ratatui::Terminal::draw
     /// [`rendering`]: crate::ui::render
-    pub fn draw(&mut self, app: Arc<RwLock<App>>) -> AppResult<()> {
-        self.terminal.draw(|frame| ui::render(app, frame))?;
+    pub async fn draw(&mut self, app: Arc<RwLock<App>>) -> AppResult<()> {
+        let app = app.read().await;
+        self.terminal.draw(|frame| ui::render(&app, frame))?;
         Ok(())
     }
 
@@ -63,7 +74,12 @@ impl<B: Backend> Tui<B> {
     /// the terminal properties if unexpected errors occur.
     fn reset() -> AppResult<()> {
         terminal::disable_raw_mode()?;
-        crossterm::execute!(io::stderr(), LeaveAlternateScreen, DisableMouseCapture)?;
+        crossterm::execute!(
+            io::stderr(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            DisableBracketedPaste
+        )?;
         Ok(())
     }
 
@@ -1,17 +1,11 @@
 pub mod running;
+pub mod theme;
 
-use std::sync::Arc;
-
-use ratatui::
-    Frame
-;
-use tokio::sync::RwLock;
+use ratatui::Frame;
 
 use crate::app::App;
 
 /// Renders the user interface widgets.
-pub fn render(app: Arc<RwLock<App>>, frame: &mut Frame) {
-    if let Ok(app) = app.try_read(){
-        app.draw(frame);
-    }
+pub fn render(app: &App, frame: &mut Frame) {
+    app.draw(frame);
 }
@@ -1,139 +1,680 @@
 use crate::{
-    app::{App, Status},
-    tcp::AccountList,
+    app::{App, Focus, Status, Toast},
+    config::Profile,
+    form::{FieldError, MasterField, MIN_MASTER_STRENGTH},
+    item::ItemType,
+    keymap::{Command, Context, KeyPress, Keymap},
+    strength::Strength,
+    tcp::{AccountList, AccountListItem, AccountUri, CustomField, CustomFieldKind},
+    ui::theme::Theme,
 };
+use chrono::{DateTime, Local, Utc};
 use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Color, Style, Stylize},
-    text::{Line, Text},
-    widgets::{Block, BorderType, Borders, List, ListItem, ListState, Paragraph},
+    style::Modifier,
+    text::{Line, Span, Text},
+    widgets::{
+        block::{Position, Title},
+        BorderType, Clear, List, ListItem, ListState, Paragraph, Tabs, Wrap,
+    },
     Frame,
 };
 use tui_textarea::TextArea;
 
+/// Passwords older than this stand out in the account list.
+const PASSWORD_MAX_AGE: chrono::TimeDelta = chrono::TimeDelta::days(365);
+
 #[derive(Debug)]
 pub struct RunningPage<'a> {
+    pub theme: Theme,
     pub login_textarea: TextArea<'a>,
     pub account_textarea: TextArea<'a>,
     pub password_textarea: TextArea<'a>,
     pub site_name_textarea: TextArea<'a>,
     pub site_url_textarea: TextArea<'a>,
     pub note_textarea: TextArea<'a>,
+    pub folder_textarea: TextArea<'a>,
+    pub tags_textarea: TextArea<'a>,
     pub search_textarea: TextArea<'a>,
+    pub bulk_tag_textarea: TextArea<'a>,
+    /// The inputs of the change master password screen, one per [`MasterField`].
+    pub master_textareas: Vec<TextArea<'a>>,
+    /// The custom fields of the edit form, in order.
+    pub custom_fields: Vec<CustomFieldInput<'a>>,
+    /// The type of the item in the edit form.
+    pub item_type: ItemType,
+    /// One text area per field of `item_type`'s schema.
+    pub type_field_textareas: Vec<TextArea<'a>>,
+}
+
+/// The text areas of one custom field in the edit form.
+#[derive(Debug)]
+pub struct CustomFieldInput<'a> {
+    pub kind: CustomFieldKind,
+    pub name: TextArea<'a>,
+    pub value: TextArea<'a>,
+}
+
+impl CustomFieldInput<'_> {
+    /// Change the kind, which is shown as the title of the value and masks hidden ones.
+    pub fn set_kind(&mut self, kind: CustomFieldKind, theme: &Theme) {
+        self.kind = kind;
+        let title = match kind {
+            CustomFieldKind::Text => "Value",
+            CustomFieldKind::Hidden => "Hidden value",
+            CustomFieldKind::Url => "URL",
+            CustomFieldKind::Boolean => "Yes/No",
+        };
+        self.value.set_block(theme.block(title));
+        if kind == CustomFieldKind::Hidden {
+            self.value.set_mask_char('\u{2022}');
+        } else {
+            self.value.clear_mask_char();
+        }
+    }
 }
 
 impl Default for RunningPage<'_> {
     fn default() -> Self {
-        let mut login_textarea = TextArea::default();
-        login_textarea.set_cursor_line_style(Style::default());
+        Self::new(Theme::default())
+    }
+}
+
+impl<'a> RunningPage<'a> {
+    pub fn new(theme: Theme) -> Self {
+        let mut login_textarea = theme.textarea("Password");
         login_textarea.set_mask_char('\u{2022}');
         login_textarea.set_placeholder_text("Enter your password");
-        login_textarea.set_block(Block::default().borders(Borders::ALL).title("Password"));
-
-        let mut account_textarea = TextArea::default();
-        account_textarea.set_block(Block::default().borders(Borders::ALL).title("Account"));
-        account_textarea.set_cursor_line_style(Style::default());
-        account_textarea.set_cursor_style(Style::default());
-
-        let mut site_name_textarea = TextArea::default();
-        site_name_textarea.set_block(Block::default().borders(Borders::ALL).title("Site Name"));
-        site_name_textarea.set_cursor_line_style(Style::default());
-        site_name_textarea.set_cursor_style(Style::default());
-
-        let mut site_url_textarea = TextArea::default();
-        site_url_textarea.set_block(Block::default().borders(Borders::ALL).title("Site URL"));
-        site_url_textarea.set_cursor_line_style(Style::default());
-        site_url_textarea.set_cursor_style(Style::default());
-
-        let mut note_textarea = TextArea::default();
-        note_textarea.set_block(Block::default().borders(Borders::ALL).title("Note"));
-        note_textarea.set_cursor_line_style(Style::default());
-        note_textarea.set_cursor_style(Style::default());
-
-        let mut password_textarea = TextArea::default();
-        password_textarea.set_block(Block::default().borders(Borders::ALL).title("Password"));
-        password_textarea.set_cursor_line_style(Style::default());
-        password_textarea.set_cursor_style(Style::default());
-
-        let mut search_textarea = TextArea::default();
-        search_textarea.set_block(Block::default().borders(Borders::ALL).title("Search"));
-        search_textarea.set_cursor_line_style(Style::default());
-        search_textarea.set_cursor_style(Style::default());
+        // The login box has the focus from the start.
+        login_textarea.set_cursor_style(theme.cursor);
+
+        let account_textarea = theme.textarea("Account");
+        let site_name_textarea = theme.textarea("Site Name");
+        let mut site_url_textarea = theme.textarea("Site URLs");
+        site_url_textarea.set_placeholder_text(
+            "One per line, optionally led by host, starts-with, exact or regex",
+        );
+        let note_textarea = theme.textarea("Note");
+        let password_textarea = theme.textarea("Password");
+        let folder_textarea = theme.textarea("Folder (e.g. Work/Infra)");
+        let tags_textarea = theme.textarea("Tags (comma separated)");
+        let search_textarea = theme.textarea("Search");
+        let mut bulk_tag_textarea = theme.textarea("Tag marked items");
+        bulk_tag_textarea.set_placeholder_text("work, mail adds them, -old removes it");
+        let master_textareas = MasterField::ALL
+            .iter()
+            .map(|field| {
+                let mut textarea = theme.textarea(field.label());
+                textarea.set_mask_char('\u{2022}');
+                textarea
+            })
+            .collect();
 
         Self {
+            theme,
             login_textarea,
             account_textarea,
             password_textarea,
             site_name_textarea,
             site_url_textarea,
             note_textarea,
+            folder_textarea,
+            tags_textarea,
             search_textarea,
+            bulk_tag_textarea,
+            master_textareas,
+            custom_fields: Vec::new(),
+            item_type: ItemType::Login,
+            type_field_textareas: Vec::new(),
         }
     }
+
+    /// Switch the edit form to `item_type`, filling its fields with `values`
+    /// in schema order. Missing values are left empty.
+    pub fn set_item_type(&mut self, item_type: ItemType, values: &[String]) {
+        self.item_type = item_type;
+        self.type_field_textareas = item_type
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(i, spec)| {
+                let mut textarea = self.theme.textarea(spec.label);
+                if spec.secret {
+                    textarea.set_mask_char('\u{2022}');
+                }
+                if let Some(value) = values.get(i) {
+                    textarea.insert_str(value);
+                }
+                textarea
+            })
+            .collect();
+        let name = if item_type == ItemType::Login {
+            "Site Name"
+        } else {
+            "Name"
+        };
+        self.site_name_textarea.set_block(self.theme.block(name));
+    }
+
+    /// Text areas for editing `field`.
+    pub fn custom_field_input(&self, field: &CustomField) -> CustomFieldInput<'a> {
+        let mut name = self.theme.textarea("Name");
+        name.insert_str(&field.name);
+        let mut value = self.theme.textarea("");
+        value.insert_str(&field.value);
+        let mut input = CustomFieldInput {
+            kind: field.kind,
+            name,
+            value,
+        };
+        input.set_kind(field.kind, &self.theme);
+        input
+    }
+}
+
+/// Where the widgets of the list screen go.
+#[derive(Debug, Clone, Copy)]
+pub struct ListAreas {
+    pub title: Rect,
+    pub search: Rect,
+    pub folders: Rect,
+    pub list: Rect,
+    pub detail: Rect,
+}
+
+/// Where the boxes of the detail view go.
+#[derive(Debug, Clone, Copy)]
+pub struct DetailAreas {
+    pub account: Rect,
+    pub site_name: Rect,
+    pub site_url: Rect,
+    pub tags: Rect,
+    pub dates: Rect,
+    pub custom_fields: Rect,
+    pub note: Rect,
+}
+
+/// Where the text areas of the edit form go.
+#[derive(Debug, Clone)]
+pub struct EditAreas {
+    /// The line naming the item type.
+    pub header: Rect,
+    /// Every field but the custom ones, in tab order.
+    pub fields: Vec<(Focus, Rect)>,
+    /// The box holding every custom field.
+    pub custom_fields: Rect,
 }
 
 impl RunningPage<'_> {
+    /// Split the terminal into the page and the footer line.
+    pub fn root_areas(area: Rect) -> (Rect, Rect) {
+        let root = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(area);
+        (root[0], root[1])
+    }
+
+    pub fn list_areas(area: Rect) -> ListAreas {
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(61), Constraint::Percentage(39)])
+            .split(area);
+
+        let menu_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Min(1),
+            ])
+            .split(chunks[0]);
+
+        let list_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
+            .split(menu_chunks[2]);
+
+        ListAreas {
+            title: menu_chunks[0],
+            search: menu_chunks[1],
+            folders: list_chunks[0],
+            list: list_chunks[1],
+            detail: chunks[1],
+        }
+    }
+
+    /// The detail view of an item with `custom_fields` custom fields.
+    pub fn detail_areas(area: Rect, custom_fields: usize) -> DetailAreas {
+        let custom_height = if custom_fields == 0 {
+            0
+        } else {
+            custom_fields.min(8) as u16 + 2
+        };
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Max(5),
+                Constraint::Max(10),
+                Constraint::Max(10),
+                Constraint::Max(4),
+                Constraint::Max(5),
+                Constraint::Length(custom_height),
+                Constraint::Min(5),
+            ])
+            .split(area);
+
+        DetailAreas {
+            account: layout[0],
+            site_name: layout[1],
+            site_url: layout[2],
+            tags: layout[3],
+            dates: layout[4],
+            custom_fields: layout[5],
+            note: layout[6],
+        }
+    }
+
+    /// The edit form of an `item_type` item.
+    pub fn edit_areas(area: Rect, item_type: ItemType) -> EditAreas {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .split(area);
+
+        let focuses: Vec<Focus> = Focus::edit_fields(item_type, 0);
+        let specs = item_type.fields();
+        let mut constraints = vec![Constraint::Length(1)];
+        constraints.extend(focuses.iter().map(|focus| match focus {
+            Focus::Password | Focus::Url => Constraint::Max(10),
+            Focus::Note => Constraint::Min(5),
+            Focus::TypeField(i) if specs[*i].multiline => Constraint::Max(10),
+            _ => Constraint::Max(5),
+        }));
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(constraints)
+            .split(columns[0]);
+
+        EditAreas {
+            header: layout[0],
+            fields: focuses
+                .into_iter()
+                .zip(layout[1..].iter().copied())
+                .collect(),
+            custom_fields: columns[1],
+        }
+    }
+
+    /// The name and value areas of each of `count` custom fields inside the
+    /// custom fields box `area`. Fields that do not fit get empty areas.
+    pub fn custom_field_areas(area: Rect, count: usize) -> Vec<(Rect, Rect)> {
+        let inner = Layout::default()
+            .margin(1)
+            .constraints([Constraint::Min(0)])
+            .split(area)[0];
+        let mut constraints = vec![Constraint::Length(3); count];
+        constraints.push(Constraint::Min(0));
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(constraints)
+            .split(inner);
+
+        rows.iter()
+            .take(count)
+            .map(|row| {
+                let columns = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+                    .split(*row);
+                (columns[0], columns[1])
+            })
+            .collect()
+    }
+
+    /// What is typed in the search box, if anything.
+    pub fn search_text(&self) -> Option<String> {
+        let text = self.search_textarea.lines().join("");
+        if text.is_empty() {
+            None
+        } else {
+            Some(text)
+        }
+    }
+
+    /// Index of the first row a list shows when its row `selected` is highlighted
+    /// in an `area` of the given size.
+    ///
+    /// The list state is rebuilt every frame, so this is how far ratatui scrolls to
+    /// bring the selection into view.
+    pub fn list_offset(area: Rect, selected: usize) -> usize {
+        let height = area.height.saturating_sub(2) as usize;
+        (selected + 1).saturating_sub(height)
+    }
+
     pub fn draw_running(&self, f: &mut Frame, app: &App) {
         // let is_login = app.login;
         let status = &app.status;
         let account_list = &app.account_list;
 
+        let (main_area, footer_area) = Self::root_areas(f.size());
+
         match status {
             Status::Login => {
-                self.draw_login(f);
+                self.draw_login(
+                    f,
+                    main_area,
+                    &app.profiles,
+                    app.profile,
+                    app.login_message().as_deref(),
+                    app.login_hint(),
+                );
             }
             Status::Delete => {
-                let account = account_list.list[account_list.selected].account.clone();
-                let site_name = match &account_list.list[account_list.selected].site_name {
-                    Some(name) => name.clone(),
-                    None => account_list.list[account_list.selected].site_url.clone(),
+                let what = match app.targets().as_slice() {
+                    [one] => account_list.list[*one].title(),
+                    many => format!("{} items", many.len()),
                 };
 
-                self.draw_delete(f, account, site_name);
-            }
-            Status::List | Status::Search => {
-                let chunks = Layout::default()
-                    .direction(Direction::Horizontal)
-                    .constraints([Constraint::Percentage(61), Constraint::Percentage(39)])
-                    .split(f.size());
-
-                let menu_chunks = Layout::default()
-                    .direction(Direction::Vertical)
-                    .constraints([
-                        Constraint::Length(3),
-                        Constraint::Length(3),
-                        Constraint::Min(1),
-                    ])
-                    .split(chunks[0]);
-
-                let detail_chunks = chunks[1];
-                let title_chunk = menu_chunks[0];
-                let search_chunk = menu_chunks[1];
-                let list_chunk = menu_chunks[2];
-
-                let search_text = if self.search_textarea.lines().join("").is_empty() {
-                    None
+                let question = if app.delete_to_trash {
+                    format!("Move {} to the trash? [Y/N]", what)
                 } else {
-                    Some(self.search_textarea.lines().join(""))
+                    format!("Delete {} permanently? This cannot be undone. [Y/N]", what)
                 };
+                self.draw_confirm(f, main_area, "Delete", question);
+            }
+            Status::Trash | Status::Purge => {
+                let areas = Self::list_areas(main_area);
+
+                self.draw_title(f, areas.title, app.profile_name());
+                self.draw_search(f, areas.search);
+                self.draw_trash(
+                    f,
+                    areas.folders.union(areas.list),
+                    &app.trash,
+                    app.trash_choice,
+                    app.trash_retention,
+                );
+                self.draw_detail(f, areas.detail, app.selected_trash_item(), 0);
+
+                if let (Status::Purge, Some(item)) = (status, app.selected_trash_item()) {
+                    self.draw_confirm(
+                        f,
+                        main_area,
+                        "Delete for good",
+                        format!(
+                            "Delete {} for good? This cannot be undone. [Y/N]",
+                            item.title()
+                        ),
+                    );
+                }
+            }
+            Status::List
+            | Status::Search
+            | Status::PickUrl
+            | Status::History
+            | Status::BulkTag
+            | Status::MasterPassword => {
+                let areas = Self::list_areas(main_area);
 
-                self.draw_title(f, title_chunk);
-                self.draw_search(f, search_chunk);
-                self.draw_list(f, list_chunk, account_list, search_text);
-                self.draw_detail(f, detail_chunks, account_list);
+                self.draw_title(f, areas.title, app.profile_name());
+                self.draw_search(f, areas.search);
+                self.draw_folders(f, areas.folders, account_list, app.folder.as_deref());
+                self.draw_list(
+                    f,
+                    areas.list,
+                    account_list,
+                    &app.visible_items(),
+                    &app.marked_items(),
+                    app.recent,
+                );
+                self.draw_detail(f, areas.detail, app.selected_item(), app.note_scroll);
+
+                match (status, app.selected_item()) {
+                    (Status::PickUrl, Some(item)) => {
+                        self.draw_pick_url(f, main_area, item, app.url_choice);
+                    }
+                    (Status::History, Some(item)) => {
+                        // Shown with its secrets, which the list may not have.
+                        let item = app.history_item.as_deref().unwrap_or(item);
+                        let custom_fields = item.custom_fields.len();
+                        let area = Self::detail_areas(areas.detail, custom_fields).note;
+                        self.draw_history(f, area, item, app.history_choice, app.reveal_history);
+                    }
+                    (Status::BulkTag, _) => {
+                        let area = self.centered_single_line_rect(60, main_area);
+                        let area = Layout::default()
+                            .margin(1)
+                            .constraints([Constraint::Min(1)])
+                            .split(area)[0];
+                        f.render_widget(Clear, area);
+                        f.render_widget(self.bulk_tag_textarea.widget(), area);
+                    }
+                    (Status::MasterPassword, _) => {
+                        self.draw_master_password(f, main_area, app.master_error.as_ref());
+                    }
+                    _ => {}
+                }
             }
             Status::Edit => {
-                self.draw_edit(f);
+                self.draw_edit(
+                    f,
+                    main_area,
+                    !app.is_edit_no_new_item,
+                    &app.form_errors,
+                    app.edit_warning.as_deref(),
+                );
+            }
+            Status::Discard => {
+                self.draw_edit(
+                    f,
+                    main_area,
+                    !app.is_edit_no_new_item,
+                    &app.form_errors,
+                    app.edit_warning.as_deref(),
+                );
+                self.draw_confirm(
+                    f,
+                    main_area,
+                    "Unsaved changes",
+                    "Discard changes? [Y/N]".to_string(),
+                );
+            }
+        }
+
+        self.draw_footer(
+            f,
+            footer_area,
+            &app.keymap,
+            app.key_context(),
+            &app.pending_keys,
+        );
+
+        if let Some(toast) = &app.toast {
+            self.draw_toast(f, main_area, toast, &app.keymap);
+        }
+
+        if app.show_help {
+            self.draw_help(f, main_area, &app.keymap, app.key_context());
+        }
+    }
+
+    /// The toast on the last line of the page, with the undo key if it can be undone.
+    fn draw_toast(&self, f: &mut Frame, area: Rect, toast: &Toast, keymap: &Keymap) {
+        let mut spans = vec![Span::styled(toast.message.as_str(), self.theme.text)];
+        if !toast.undo.is_empty() {
+            let undo = keymap
+                .hints(Context::List)
+                .into_iter()
+                .find(|(_, command)| *command == Command::Undo);
+            if let Some((keys, _)) = undo {
+                spans.push(Span::raw("  "));
+                spans.push(Span::styled(keys, self.theme.key));
+                spans.push(Span::styled(" undo", self.theme.text));
+            }
+        }
+        let line = Line::from(spans);
+        let width = (line.width() as u16 + 2).min(area.width);
+        let area = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.bottom().saturating_sub(1),
+            width,
+            height: 1.min(area.height),
+        };
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(line)
+                .alignment(Alignment::Center)
+                .style(self.theme.highlight),
+            area,
+        );
+    }
+
+    /// One line of the most relevant keys for the current context,
+    /// led by the keys of an unfinished sequence.
+    fn draw_footer(
+        &self,
+        f: &mut Frame,
+        area: Rect,
+        keymap: &Keymap,
+        context: Context,
+        pending: &[KeyPress],
+    ) {
+        let mut spans = Vec::new();
+        if !pending.is_empty() {
+            let keys = pending.iter().map(KeyPress::label).collect::<Vec<_>>();
+            spans.push(Span::styled(
+                format!("{} …", keys.join(" ")),
+                self.theme.key.add_modifier(Modifier::REVERSED),
+            ));
+        }
+        for (keys, command) in keymap.hints(context) {
+            if !spans.is_empty() {
+                spans.push(Span::raw("  "));
+            }
+            spans.push(Span::styled(keys, self.theme.key));
+            spans.push(Span::styled(
+                format!(" {}", command.description()),
+                self.theme.text,
+            ));
+        }
+        f.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+
+    /// Overlay listing every binding, grouped by context, current context first.
+    fn draw_help(&self, f: &mut Frame, area: Rect, keymap: &Keymap, current: Context) {
+        let area = self.centered_rect(60, 80, area);
+
+        let mut contexts = vec![current];
+        contexts.extend(Context::ALL.iter().filter(|c| **c != current));
+
+        let mut lines = Vec::new();
+        for context in contexts {
+            if !lines.is_empty() {
+                lines.push(Line::default());
+            }
+            lines.push(Line::from(Span::styled(
+                context.title(),
+                self.theme.title.add_modifier(Modifier::BOLD),
+            )));
+            for (keys, command) in keymap.hints(context) {
+                lines.push(Line::from(vec![
+                    Span::styled(format!("  {:<16}", keys), self.theme.key),
+                    Span::styled(command.description(), self.theme.text),
+                ]));
             }
         }
+
+        let paragraph = Paragraph::new(lines)
+            .block(
+                self.theme
+                    .block("Help - press any key to close")
+                    .border_type(BorderType::Rounded),
+            )
+            .wrap(Wrap { trim: false });
+        f.render_widget(Clear, area);
+        f.render_widget(paragraph, area);
     }
 
     fn draw_search(&self, f: &mut Frame, area: Rect) {
         f.render_widget(self.search_textarea.widget(), area);
     }
 
-    fn draw_delete(&self, f: &mut Frame, account: String, site_name: String) {
-        let area = self.centered_single_line_rect(60, f.size());
+    /// The change master password box: the inputs, a strength meter for the
+    /// new password and what is wrong with them, if anything.
+    fn draw_master_password(
+        &self,
+        f: &mut Frame,
+        area: Rect,
+        error: Option<&(MasterField, String)>,
+    ) {
+        let area = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),
+                Constraint::Length(13),
+                Constraint::Min(0),
+            ])
+            .split(area)[1];
+        let area = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(20),
+                Constraint::Percentage(60),
+                Constraint::Percentage(20),
+            ])
+            .split(area)[1];
+        let block = self
+            .theme
+            .block("Change master password")
+            .border_type(BorderType::Rounded);
+        let inner = block.inner(area);
+        f.render_widget(Clear, area);
+        f.render_widget(block, area);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(1),
+                Constraint::Length(1),
+            ])
+            .split(inner);
+        for (textarea, row) in self.master_textareas.iter().zip(rows.iter()) {
+            f.render_widget(textarea.widget(), *row);
+        }
+
+        let new = self.master_textareas[MasterField::New as usize]
+            .lines()
+            .join("");
+        let strength = Strength::of(&new);
+        let style = if strength < MIN_MASTER_STRENGTH {
+            self.theme.error
+        } else {
+            self.theme.success
+        };
+        let meter = Line::from(vec![
+            Span::styled("Strength ", self.theme.text),
+            Span::styled("\u{2588}".repeat(strength.level()), style),
+            Span::styled("\u{2591}".repeat(5 - strength.level()), self.theme.text),
+            Span::styled(format!(" {}", strength.label()), style),
+        ]);
+        f.render_widget(Paragraph::new(meter), rows[3]);
+
+        if let Some((field, message)) = error {
+            let error = format!("{}: {}", field.label(), message);
+            f.render_widget(
+                Paragraph::new(Span::styled(error, self.theme.error)),
+                rows[4],
+            );
+        }
+    }
+
+    /// A yes/no question in a box in the middle of `area`.
+    fn draw_confirm(&self, f: &mut Frame, area: Rect, title: &str, question: String) {
+        let area = self.centered_single_line_rect(60, area);
         // f.render_widget(popup_block, area);
 
         let layout = Layout::default()
@@ -141,73 +682,127 @@ impl RunningPage<'_> {
             .constraints([Constraint::Min(1)])
             .split(area);
 
-        let text = Text::styled(
-            format!("Delete {} - {}? [Y/N]", account, site_name),
-            Style::default(),
-        );
+        let text = Text::styled(question, self.theme.text);
         let paragraph = Paragraph::new(text)
-            .block(
-                Block::default()
-                    .title("Delete")
-                    .borders(Borders::ALL)
-                    .border_type(BorderType::Rounded),
-            )
+            .block(self.theme.block(title).border_type(BorderType::Rounded))
             .alignment(Alignment::Center);
+        f.render_widget(Clear, layout[0]);
         f.render_widget(paragraph, layout[0]);
     }
 
-    fn draw_edit(&self, f: &mut Frame) {
-        let layout = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Max(5),
-                Constraint::Max(5),
-                Constraint::Max(10),
-                Constraint::Max(10),
-                Constraint::Min(5),
-            ])
-            .split(f.size());
-
-        let account_area = layout[0];
-        let site_name_area = layout[1];
-        let password_area = layout[2];
-        let site_url_area = layout[3];
-        let note_area = layout[4];
+    fn draw_edit(
+        &self,
+        f: &mut Frame,
+        area: Rect,
+        is_new: bool,
+        errors: &[FieldError],
+        warning: Option<&str>,
+    ) {
+        let areas = Self::edit_areas(area, self.item_type);
 
-        f.render_widget(
-            // account_paragraph.block(block.clone().title("Account")),
-            self.account_textarea.widget(),
-            account_area,
-        );
-        f.render_widget(
-            // account_paragraph.block(block.clone().title("Account")),
-            self.password_textarea.widget(),
-            password_area,
-        );
-        f.render_widget(
-            // site_url_paragraph.block(block.clone().title("Site URL")),
-            self.site_url_textarea.widget(),
-            site_url_area,
-        );
-        f.render_widget(
-            // site_name_paragraph.block(block.clone().title("Site Name")),
-            self.site_name_textarea.widget(),
-            site_name_area,
-        );
-        f.render_widget(
-            // note_paragraph.block(block.clone().title("Note")),
-            self.note_textarea.widget(),
-            note_area,
+        let header = format!(
+            "{} {} {}",
+            self.item_type.icon(),
+            if is_new { "New" } else { "Edit" },
+            self.item_type.label().to_lowercase()
         );
+        let mut header = vec![Span::styled(header, self.theme.title)];
+        if let Some(warning) = warning {
+            header.push(Span::raw("  "));
+            header.push(Span::styled(warning, self.theme.error));
+        }
+        f.render_widget(Paragraph::new(Line::from(header)), areas.header);
+
+        for (field, area) in areas.fields {
+            let textarea = match field {
+                Focus::Account => &self.account_textarea,
+                Focus::Password => &self.password_textarea,
+                Focus::Url => &self.site_url_textarea,
+                Focus::Name => &self.site_name_textarea,
+                Focus::Folder => &self.folder_textarea,
+                Focus::Tags => &self.tags_textarea,
+                Focus::Note => &self.note_textarea,
+                Focus::TypeField(i) => &self.type_field_textareas[i],
+                Focus::List | Focus::CustomName(_) | Focus::CustomValue(_) => continue,
+            };
+            match errors.iter().find(|e| e.field == field) {
+                Some(error) => {
+                    let (input_area, error_area) = Self::split_error_line(area);
+                    f.render_widget(textarea.widget(), input_area);
+                    f.render_widget(
+                        Paragraph::new(Span::styled(error.message.as_str(), self.theme.error)),
+                        error_area,
+                    );
+                }
+                None => f.render_widget(textarea.widget(), area),
+            }
+        }
+
+        self.draw_custom_field_inputs(f, areas.custom_fields, errors);
+    }
+
+    /// The custom fields box of the edit form, with the first of their errors
+    /// at the bottom; there is no room for one under every field.
+    fn draw_custom_field_inputs(&self, f: &mut Frame, area: Rect, errors: &[FieldError]) {
+        let mut block = self
+            .theme
+            .block("Custom Fields")
+            .border_type(BorderType::Rounded);
+        let error = errors.iter().find_map(|e| match e.field {
+            Focus::CustomName(i) | Focus::CustomValue(i) => {
+                Some(format!("Field {}: {}", i + 1, e.message))
+            }
+            _ => None,
+        });
+        if let Some(error) = error {
+            block = block.title(
+                Title::from(Span::styled(error, self.theme.error)).position(Position::Bottom),
+            );
+        }
+
+        if self.custom_fields.is_empty() {
+            let hint = Paragraph::new(Text::styled("No custom fields", self.theme.text))
+                .block(block)
+                .alignment(Alignment::Center);
+            f.render_widget(hint, area);
+            return;
+        }
+        f.render_widget(block, area);
+
+        let areas = Self::custom_field_areas(area, self.custom_fields.len());
+        for (input, (name_area, value_area)) in self.custom_fields.iter().zip(areas) {
+            f.render_widget(input.name.widget(), name_area);
+            f.render_widget(input.value.widget(), value_area);
+        }
+    }
+
+    /// Take the last line of a field's area for its error message.
+    pub fn split_error_line(area: Rect) -> (Rect, Rect) {
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(area);
+        (layout[0], layout[1])
     }
 
-    fn draw_login(&self, f: &mut Frame) {
+    /// The password box, with the profiles to pick from above it if there are
+    /// several, and why unlocking failed and any keyboard `hint` below it.
+    fn draw_login(
+        &self,
+        f: &mut Frame,
+        area: Rect,
+        profiles: &[Profile],
+        current: usize,
+        message: Option<&str>,
+        hint: Option<&str>,
+    ) {
+        let outer = area;
         // let popup_block = Block::default()
         //     .borders(Borders::NONE)
         //     .border_type(BorderType::Rounded)
         //     .style(Style::default().bg(Color::Black));
 
-        let area = self.centered_single_line_rect(60, f.size());
+        let area = self.centered_single_line_rect(60, area);
         // f.render_widget(popup_block, area);
 
         let layout = Layout::default()
@@ -216,117 +811,206 @@ impl RunningPage<'_> {
             .split(area);
 
         f.render_widget(self.login_textarea.widget(), layout[0]);
+
+        if profiles.len() > 1 {
+            let picker = Rect {
+                y: area.y,
+                height: 1,
+                ..layout[0]
+            };
+            let tabs = Tabs::new(profiles.iter().map(|profile| profile.name.as_str()))
+                .select(current)
+                .style(self.theme.text)
+                .highlight_style(self.theme.highlight);
+            f.render_widget(tabs, picker);
+        }
+
+        let lines = [(message, self.theme.error), (hint, self.theme.title)];
+        let mut y = layout[0].bottom();
+        for (text, style) in lines {
+            let Some(text) = text else {
+                continue;
+            };
+            if y >= outer.bottom() {
+                break;
+            }
+            let line = Rect {
+                y,
+                height: 1,
+                ..layout[0]
+            };
+            f.render_widget(Paragraph::new(Line::styled(text, style)), line);
+            y += 1;
+        }
     }
 
-    fn draw_title(&self, f: &mut Frame, area: Rect) {
-        let block = Block::default()
-            // .title("You Should Not Pass")
-            .borders(Borders::ALL)
-            .border_type(BorderType::Rounded);
+    /// The title bar, naming the profile shown if there are several.
+    fn draw_title(&self, f: &mut Frame, area: Rect, profile: Option<&str>) {
+        let block = self.theme.block("").border_type(BorderType::Rounded);
 
-        let title = Paragraph::new(Text::styled("You Should Not Pass", Style::default()))
+        let text = match profile {
+            Some(name) => format!("You Should Not Pass · {}", name),
+            None => "You Should Not Pass".to_string(),
+        };
+        let title = Paragraph::new(Text::styled(text, self.theme.title))
             .block(block)
             .alignment(Alignment::Center);
         f.render_widget(title, area);
     }
 
+    /// The rows of the folder tree: every item first, then each folder.
+    pub fn folder_rows(account_list: &AccountList) -> Vec<Option<String>> {
+        let mut rows = vec![None];
+        rows.extend(account_list.folders().into_iter().map(Some));
+        rows
+    }
+
+    fn draw_folders(
+        &self,
+        f: &mut Frame,
+        area: Rect,
+        account_list: &AccountList,
+        current: Option<&str>,
+    ) {
+        let rows = Self::folder_rows(account_list);
+        let items: Vec<ListItem> = rows
+            .iter()
+            .map(|row| {
+                let text = match row {
+                    None => "All".to_string(),
+                    Some(path) => {
+                        let depth = path.matches('/').count();
+                        let name = path.rsplit('/').next().unwrap_or(path);
+                        format!("{}{}", "  ".repeat(depth), name)
+                    }
+                };
+                ListItem::new(Line::styled(text, self.theme.text))
+            })
+            .collect();
+        let selected = rows.iter().position(|row| row.as_deref() == current);
+
+        let list = List::new(items)
+            .block(self.theme.block("Folders").border_type(BorderType::Rounded))
+            .highlight_style(self.theme.highlight);
+        f.render_stateful_widget(
+            list,
+            area,
+            &mut ListState::default().with_selected(selected),
+        );
+    }
+
     fn draw_list(
         &self,
         f: &mut Frame,
         area: Rect,
         account_list: &AccountList,
-        search_text: Option<String>,
+        visible: &[usize],
+        marked: &[usize],
+        recent: bool,
     ) {
+        let now = Utc::now();
         let mut list_items = Vec::<ListItem>::new();
-        for item in &account_list.list {
-            let account = item.account.clone();
-            let name = match &item.site_name {
-                Some(name) => name,
-                None => &item.site_url.clone(),
-            };
-
+        for (i, item) in visible.iter().map(|i| (*i, &account_list.list[*i])) {
             let is_dead = item.is_dead;
+            let is_old = item
+                .password_age(now)
+                .is_some_and(|age| age > PASSWORD_MAX_AGE);
+            let mark = if marked.contains(&i) { "\u{25cf}" } else { " " };
+            let text = format!("{} {}", item.item_type.icon(), item.title());
 
-            let text = format!("{} - {}", account, name);
-            if let Some(search_text) = &search_text {
-                if !text.to_lowercase().contains(&search_text.to_lowercase()) {
-                    continue;
-                }
-            }
-
-            list_items.push(ListItem::new(Line::from(if is_dead {
-                text.crossed_out().italic().dark_gray()
-            } else {
-                text.white()
-            })));
+            list_items.push(ListItem::new(Line::from(vec![
+                Span::styled(format!("{} ", mark), self.theme.key),
+                Span::styled(
+                    text,
+                    if is_dead {
+                        self.theme.dead_link
+                    } else if is_old {
+                        self.theme.old_password
+                    } else {
+                        self.theme.text
+                    },
+                ),
+            ])));
         }
 
+        let title = if recent {
+            "Recently Used"
+        } else {
+            "Account List"
+        };
+        let title = match marked.len() {
+            0 => title.to_string(),
+            n => format!("{} ({} marked)", title, n),
+        };
         let list = List::new(list_items)
-            .block(
-                Block::default()
-                    .title("Account List")
-                    .borders(Borders::ALL)
-                    .border_type(BorderType::Rounded),
-            )
+            .block(self.theme.block(&title).border_type(BorderType::Rounded))
             .highlight_symbol("> ")
-            .highlight_style(Style::default().fg(Color::Yellow));
+            .highlight_style(self.theme.highlight);
 
-        let selected = account_list.selected;
+        let selected = visible.iter().position(|i| *i == account_list.selected);
 
         f.render_stateful_widget(
             list,
             area,
-            &mut ListState::default().with_selected(Some(selected)),
+            &mut ListState::default().with_selected(selected),
         );
     }
 
-    fn draw_detail(&self, f: &mut Frame, area: Rect, account_list: &AccountList) {
-        let selected = account_list.selected;
-
-        let layout = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Max(5),
-                Constraint::Max(10),
-                Constraint::Max(10),
-                Constraint::Min(5),
-            ])
-            .split(area);
-
-        let account_area = layout[0];
-        let site_name_area = layout[1];
-        let site_url_area = layout[2];
-        let note_area = layout[3];
+    fn draw_detail(
+        &self,
+        f: &mut Frame,
+        area: Rect,
+        item: Option<&AccountListItem>,
+        note_scroll: u16,
+    ) {
+        let custom_fields = item.map_or(0, |i| i.custom_fields.len());
+        let areas = Self::detail_areas(area, custom_fields);
+        let account_area = areas.account;
+        let site_name_area = areas.site_name;
+        let site_url_area = areas.site_url;
+        let note_area = areas.note;
 
-        let block = Block::default()
-            .borders(Borders::NONE)
-            .style(Style::default())
-            .borders(Borders::ALL)
-            .border_type(BorderType::Rounded);
+        let block = self.theme.block("").border_type(BorderType::Rounded);
 
         // f.render_widget(block, account_area);
         // f.render_widget(block, site_name_area);
         // f.render_widget(block, site_url_area);
         // f.render_widget(block, note_area);
 
-        let item = account_list.list.get(selected);
-
         if let Some(i) = item {
-            let account = i.account.clone();
-            let site_url = i.site_url.clone();
+            let is_login = i.item_type == ItemType::Login;
+            let account = if is_login {
+                i.account.clone()
+            } else {
+                i.item_type.label().to_string()
+            };
+            let site_url = if !is_login {
+                self.type_field_lines(i)
+            } else if i.uris.is_empty() {
+                i.site_url.clone()
+            } else {
+                let lines: Vec<String> = i.uris.iter().map(AccountUri::to_line).collect();
+                lines.join("\n")
+            };
             let site_name = match &i.site_name {
                 Some(name) => name.clone(),
-                None => site_url.clone(),
+                None => i.site_url.clone(),
             };
             let note = match &i.note {
                 Some(note) => note.clone(),
                 None => "".to_string(),
             };
 
-            let account_paragraph = Paragraph::new(Text::styled(account, Style::default()));
-            let site_url_paragraph = Paragraph::new(Text::styled(site_url, Style::default()));
-            let site_name_paragraph = Paragraph::new(Text::styled(site_name, Style::default()));
-            let note_paragraph = Paragraph::new(Text::styled(note, Style::default()));
+            let account_paragraph = Paragraph::new(Text::styled(account, self.theme.text));
+            let site_url_paragraph = Paragraph::new(Text::styled(site_url, self.theme.text));
+            let (account_title, site_name_title, site_url_title) = if is_login {
+                ("Account", "Site Name", "Site URL")
+            } else {
+                ("Type", "Name", "Details")
+            };
+            let site_name_paragraph = Paragraph::new(Text::styled(site_name, self.theme.text));
+            let note_paragraph =
+                Paragraph::new(Text::styled(note, self.theme.text)).scroll((note_scroll, 0));
 
             // self.account_textarea.insert_str(account);
             // self.site_url_textarea.insert_str(site_url);
@@ -334,34 +1018,237 @@ impl RunningPage<'_> {
             // self.note_textarea.insert_str(note);
 
             f.render_widget(
-                account_paragraph.block(block.clone().title("Account")),
+                account_paragraph.block(block.clone().title(account_title)),
                 // self.account_textarea.widget(),
                 account_area,
             );
             f.render_widget(
-                site_url_paragraph.block(block.clone().title("Site URL")),
+                site_url_paragraph.block(block.clone().title(site_url_title)),
                 // self.site_url_textarea.widget(),
                 site_url_area,
             );
             f.render_widget(
-                site_name_paragraph.block(block.clone().title("Site Name")),
+                site_name_paragraph.block(block.clone().title(site_name_title)),
                 // self.site_name_textarea.widget(),
                 site_name_area,
             );
+            let folder = i.folder.clone().unwrap_or("-".to_string());
+            let tags = i
+                .tags
+                .iter()
+                .map(|tag| format!("#{}", tag))
+                .collect::<Vec<_>>()
+                .join(" ");
+            f.render_widget(
+                Paragraph::new(vec![
+                    Line::styled(folder, self.theme.text),
+                    Line::styled(tags, self.theme.key),
+                ])
+                .block(block.clone().title("Folder & Tags")),
+                areas.tags,
+            );
+            let date = |label: &str, time: Option<DateTime<Utc>>, missing: &str| {
+                let time = match time {
+                    Some(time) => time
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string(),
+                    None => missing.to_string(),
+                };
+                Line::from(vec![
+                    Span::styled(format!("{:<11}", label), self.theme.title),
+                    Span::styled(time, self.theme.text),
+                ])
+            };
+            f.render_widget(
+                Paragraph::new(vec![
+                    date("Created", i.created_at, "-"),
+                    date("Modified", i.modified_at, "-"),
+                    date("Last used", i.last_used_at, "never"),
+                ])
+                .block(block.clone().title("Dates")),
+                areas.dates,
+            );
+            if !i.custom_fields.is_empty() {
+                let lines: Vec<Line> = i
+                    .custom_fields
+                    .iter()
+                    .map(|field| {
+                        let value = match field.kind {
+                            CustomFieldKind::Text | CustomFieldKind::Url => field.value.clone(),
+                            CustomFieldKind::Hidden => "\u{2022}".repeat(8),
+                            CustomFieldKind::Boolean => match field.value.as_str() {
+                                "true" => "yes".to_string(),
+                                _ => "no".to_string(),
+                            },
+                        };
+                        Line::from(vec![
+                            Span::styled(format!("{}: ", field.name), self.theme.title),
+                            Span::styled(value, self.theme.text),
+                        ])
+                    })
+                    .collect();
+                f.render_widget(
+                    Paragraph::new(lines).block(block.clone().title("Custom Fields")),
+                    areas.custom_fields,
+                );
+            }
             f.render_widget(
                 note_paragraph.block(block.clone().title("Note")),
                 // self.note_textarea.widget(),
                 note_area,
             );
         } else {
-            let detail = Paragraph::new(Text::styled("No item selected", Style::default()))
+            let detail = Paragraph::new(Text::styled("No item selected", self.theme.text))
                 .block(block)
                 .alignment(Alignment::Center);
             f.render_widget(detail, area);
         };
     }
+    /// The typed fields of a non-login item as `Label: value` lines. Secrets are
+    /// masked, except for the last four digits of a card number.
+    fn type_field_lines(&self, item: &AccountListItem) -> String {
+        let lines: Vec<String> = item
+            .item_type
+            .fields()
+            .iter()
+            .filter(|spec| !item.type_field(spec.key).is_empty())
+            .map(|spec| {
+                let value = item.type_field(spec.key);
+                let value = match (item.item_type, spec.key) {
+                    (ItemType::Card, "number") => {
+                        let last: String =
+                            value.chars().skip(value.len().saturating_sub(4)).collect();
+                        format!("{} {}", "\u{2022}".repeat(4), last)
+                    }
+                    _ if spec.secret => "\u{2022}".repeat(8),
+                    _ => value.to_string(),
+                };
+                format!("{}: {}", spec.label, value)
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /// The deleted items, most recently deleted first, with when they are purged.
+    fn draw_trash(
+        &self,
+        f: &mut Frame,
+        area: Rect,
+        trash: &[AccountListItem],
+        choice: usize,
+        retention: Option<chrono::Duration>,
+    ) {
+        let block = self.theme.block("Trash").border_type(BorderType::Rounded);
+        if trash.is_empty() {
+            let hint = Paragraph::new(Text::styled("The trash is empty", self.theme.text))
+                .block(block)
+                .alignment(Alignment::Center);
+            f.render_widget(hint, area);
+            return;
+        }
+
+        let now = Utc::now();
+        let items: Vec<ListItem> = trash
+            .iter()
+            .map(|item| {
+                let left = match retention.and_then(|r| item.purged_at(r)) {
+                    Some(purged_at) => {
+                        let days = (purged_at - now).num_days().max(0);
+                        format!("purged in {} days", days)
+                    }
+                    None => "kept".to_string(),
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(
+                        format!("{} {}", item.item_type.icon(), item.title()),
+                        self.theme.text,
+                    ),
+                    Span::styled(format!("  ({})", left), self.theme.key),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(block)
+            .highlight_symbol("> ")
+            .highlight_style(self.theme.highlight);
+        f.render_stateful_widget(
+            list,
+            area,
+            &mut ListState::default().with_selected(Some(choice)),
+        );
+    }
+
+    /// The old passwords of `item`, newest first, over the note of the detail view.
+    fn draw_history(
+        &self,
+        f: &mut Frame,
+        area: Rect,
+        item: &AccountListItem,
+        choice: usize,
+        reveal: bool,
+    ) {
+        let items: Vec<ListItem> = item
+            .password_history
+            .iter()
+            .map(|entry| {
+                let changed_at = entry.changed_at.with_timezone(&Local);
+                let password = if reveal {
+                    entry.password.clone()
+                } else {
+                    "\u{2022}".repeat(8)
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(
+                        format!("{}  ", changed_at.format("%Y-%m-%d %H:%M")),
+                        self.theme.title,
+                    ),
+                    Span::styled(password, self.theme.text),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                self.theme
+                    .block("Password History")
+                    .border_type(BorderType::Rounded),
+            )
+            .highlight_symbol("> ")
+            .highlight_style(self.theme.highlight);
+        f.render_widget(Clear, area);
+        f.render_stateful_widget(
+            list,
+            area,
+            &mut ListState::default().with_selected(Some(choice)),
+        );
+    }
+
+    /// The URLs of `item` that can be opened, `choice` highlighted.
+    fn draw_pick_url(&self, f: &mut Frame, area: Rect, item: &AccountListItem, choice: usize) {
+        let area = self.centered_rect(60, 40, area);
+        let items: Vec<ListItem> = item
+            .visitable_uris()
+            .into_iter()
+            .map(|uri| ListItem::new(Line::styled(uri.uri.clone(), self.theme.text)))
+            .collect();
+        let list = List::new(items)
+            .block(
+                self.theme
+                    .block("Open URL")
+                    .border_type(BorderType::Rounded),
+            )
+            .highlight_symbol("> ")
+            .highlight_style(self.theme.highlight);
+        f.render_widget(Clear, area);
+        f.render_stateful_widget(
+            list,
+            area,
+            &mut ListState::default().with_selected(Some(choice)),
+        );
+    }
+
     /// helper function to create a centered rect using up certain percentage of the available rect `r`
-    fn _centered_rect(&self, percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    fn centered_rect(&self, percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         // Cut the given rectangle into three vertical pieces
         let popup_layout = Layout::default()
             .direction(Direction::Vertical)
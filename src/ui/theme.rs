@@ -0,0 +1,268 @@
+use std::str::FromStr;
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders},
+};
+use serde::Deserialize;
+use tui_textarea::TextArea;
+
+use crate::app::AppResult;
+
+/// The built-in themes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+    NoColor,
+}
+
+/// The `[theme]` section of the config file.
+///
+/// ```toml
+/// [theme]
+/// name = "light"
+///
+/// [theme.colors]
+/// highlight = "blue"
+/// border = "#888888"
+/// ```
+///
+/// Colors are anything ratatui can parse: a name, `#rrggbb` or a 0-255 index.
+/// `NO_COLOR` in the environment wins over all of it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub name: ThemeName,
+    pub colors: ThemeColors,
+}
+
+/// Per-color overrides on top of the chosen theme.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeColors {
+    pub text: Option<String>,
+    pub border: Option<String>,
+    pub title: Option<String>,
+    pub highlight: Option<String>,
+    pub dead_link: Option<String>,
+    pub old_password: Option<String>,
+    pub key: Option<String>,
+    pub error: Option<String>,
+    pub success: Option<String>,
+    pub cursor: Option<String>,
+}
+
+/// Styles for every widget of the [`RunningPage`](super::running::RunningPage).
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// Plain text.
+    pub text: Style,
+    /// Widget borders.
+    pub border: Style,
+    /// Widget titles and headings.
+    pub title: Style,
+    /// The selected item of the list.
+    pub highlight: Style,
+    /// Items whose link is dead.
+    pub dead_link: Style,
+    /// Items whose password has not been changed for a long time.
+    pub old_password: Style,
+    /// Keys in the footer and help.
+    pub key: Style,
+    pub error: Style,
+    pub success: Style,
+    /// The cursor of the focused text area.
+    pub cursor: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            text: Style::default().fg(Color::White),
+            border: Style::default(),
+            title: Style::default(),
+            highlight: Style::default().fg(Color::Yellow),
+            dead_link: Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::CROSSED_OUT | Modifier::ITALIC),
+            old_password: Style::default().fg(Color::LightRed),
+            key: Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+            error: Style::default().fg(Color::Red),
+            success: Style::default().fg(Color::Green),
+            cursor: Style::default().bg(Color::White),
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            text: Style::default().fg(Color::Black),
+            border: Style::default().fg(Color::Gray),
+            title: Style::default()
+                .fg(Color::Black)
+                .add_modifier(Modifier::BOLD),
+            highlight: Style::default().fg(Color::Blue),
+            dead_link: Style::default()
+                .fg(Color::Gray)
+                .add_modifier(Modifier::CROSSED_OUT | Modifier::ITALIC),
+            old_password: Style::default().fg(Color::Red),
+            key: Style::default()
+                .fg(Color::Magenta)
+                .add_modifier(Modifier::BOLD),
+            error: Style::default().fg(Color::Red),
+            success: Style::default().fg(Color::Green),
+            cursor: Style::default().bg(Color::Black),
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            text: Style::default().fg(Color::White).bg(Color::Black),
+            border: Style::default().fg(Color::White),
+            title: Style::default()
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            highlight: Style::default()
+                .fg(Color::Black)
+                .bg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+            dead_link: Style::default()
+                .fg(Color::LightRed)
+                .add_modifier(Modifier::CROSSED_OUT),
+            old_password: Style::default()
+                .fg(Color::LightMagenta)
+                .add_modifier(Modifier::BOLD),
+            key: Style::default()
+                .fg(Color::LightYellow)
+                .add_modifier(Modifier::BOLD),
+            error: Style::default()
+                .fg(Color::LightRed)
+                .add_modifier(Modifier::BOLD),
+            success: Style::default()
+                .fg(Color::LightGreen)
+                .add_modifier(Modifier::BOLD),
+            cursor: Style::default().add_modifier(Modifier::REVERSED),
+        }
+    }
+
+    /// Modifiers only, for terminals (and users) that want no color.
+    pub fn no_color() -> Self {
+        Self {
+            text: Style::default(),
+            border: Style::default(),
+            title: Style::default().add_modifier(Modifier::BOLD),
+            highlight: Style::default().add_modifier(Modifier::REVERSED),
+            dead_link: Style::default().add_modifier(Modifier::CROSSED_OUT | Modifier::ITALIC),
+            old_password: Style::default().add_modifier(Modifier::UNDERLINED),
+            key: Style::default().add_modifier(Modifier::BOLD),
+            error: Style::default().add_modifier(Modifier::BOLD),
+            success: Style::default(),
+            cursor: Style::default().add_modifier(Modifier::REVERSED),
+        }
+    }
+
+    pub fn from_name(name: ThemeName) -> Self {
+        match name {
+            ThemeName::Dark => Self::dark(),
+            ThemeName::Light => Self::light(),
+            ThemeName::HighContrast => Self::high_contrast(),
+            ThemeName::NoColor => Self::no_color(),
+        }
+    }
+
+    /// Build the theme from the config, honoring `NO_COLOR`.
+    pub fn from_config(config: &ThemeConfig) -> AppResult<Self> {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        Self::build(config, no_color)
+    }
+
+    fn build(config: &ThemeConfig, no_color: bool) -> AppResult<Self> {
+        if no_color {
+            return Ok(Self::no_color());
+        }
+        let mut theme = Self::from_name(config.name);
+        let colors = &config.colors;
+        let foregrounds = [
+            (&colors.text, &mut theme.text),
+            (&colors.border, &mut theme.border),
+            (&colors.title, &mut theme.title),
+            (&colors.highlight, &mut theme.highlight),
+            (&colors.dead_link, &mut theme.dead_link),
+            (&colors.old_password, &mut theme.old_password),
+            (&colors.key, &mut theme.key),
+            (&colors.error, &mut theme.error),
+            (&colors.success, &mut theme.success),
+        ];
+        for (color, style) in foregrounds {
+            if let Some(color) = color {
+                *style = style.fg(parse_color(color)?);
+            }
+        }
+        if let Some(color) = &colors.cursor {
+            theme.cursor = Style::default().bg(parse_color(color)?);
+        }
+        Ok(theme)
+    }
+
+    /// A bordered block with the theme's border and title styles.
+    pub fn block<'a>(&self, title: &'a str) -> Block<'a> {
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(self.border)
+            .title_style(self.title)
+            .title(title)
+    }
+
+    /// An unfocused text area inside [`Theme::block`].
+    pub fn textarea<'a>(&self, title: &'a str) -> TextArea<'a> {
+        let mut textarea = TextArea::default();
+        textarea.set_block(self.block(title));
+        textarea.set_style(self.text);
+        textarea.set_cursor_line_style(Style::default());
+        textarea.set_cursor_style(Style::default());
+        textarea
+    }
+}
+
+fn parse_color(color: &str) -> AppResult<Color> {
+    Color::from_str(color).map_err(|_| format!("Unknown color `{}`", color).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_config() {
+        let config: ThemeConfig = toml::from_str(
+            r##"
+            name = "light"
+            [colors]
+            highlight = "#ff0000"
+            cursor = "green"
+            "##,
+        )
+        .unwrap();
+        let theme = Theme::build(&config, false).unwrap();
+        assert_eq!(theme.highlight.fg, Some(Color::Rgb(0xff, 0, 0)));
+        assert_eq!(theme.cursor.bg, Some(Color::Green));
+        assert_eq!(theme.key.fg, Theme::light().key.fg);
+
+        let theme = Theme::build(&config, true).unwrap();
+        assert_eq!(theme.highlight.fg, None);
+
+        let config: ThemeConfig = toml::from_str("[colors]\ntext = \"blurple\"").unwrap();
+        assert!(Theme::build(&config, false).is_err());
+    }
+}